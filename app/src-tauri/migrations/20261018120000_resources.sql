@@ -0,0 +1,16 @@
+CREATE TABLE resources (
+  id             INTEGER NOT NULL PRIMARY KEY,
+  session_id     TEXT    NOT NULL REFERENCES sessions(session_id),
+
+  ts_ms          INTEGER NOT NULL,
+  pid            INTEGER NOT NULL,
+
+  cpu_user_ms    INTEGER NOT NULL,
+  cpu_system_ms  INTEGER NOT NULL,
+  rss_bytes      INTEGER NOT NULL,
+  threads        INTEGER NOT NULL,
+  open_fds       INTEGER NOT NULL
+);
+
+CREATE INDEX resources_by_session
+  ON resources(session_id, ts_ms);
@@ -0,0 +1,6 @@
+ALTER TABLE records ADD COLUMN trace_id TEXT;
+ALTER TABLE records ADD COLUMN trace_span_id TEXT;
+ALTER TABLE records ADD COLUMN trace_parent_id TEXT;
+
+CREATE INDEX records_by_trace
+  ON records(trace_id, ts_ms);
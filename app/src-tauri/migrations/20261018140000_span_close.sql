@@ -0,0 +1,8 @@
+ALTER TABLE records ADD COLUMN end_ms INTEGER;
+ALTER TABLE records ADD COLUMN duration_ns INTEGER;
+ALTER TABLE records ADD COLUMN busy_ns INTEGER;
+ALTER TABLE records ADD COLUMN idle_ns INTEGER;
+ALTER TABLE records ADD COLUMN follows_from_json TEXT;
+
+CREATE INDEX records_by_span
+  ON records(identity_pk, span_id);
@@ -0,0 +1,2 @@
+-- JSON object of the writer's labels, filter with `json_extract(labels_json, '$."git.branch"')`.
+ALTER TABLE identity ADD COLUMN labels_json TEXT NOT NULL DEFAULT '{}';
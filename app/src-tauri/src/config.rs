@@ -75,6 +75,7 @@ impl Config {
                 key: KeySource::File {
                     path: Self::default_key_path(dir),
                 },
+                ..ReaderConfig::default()
             },
             settings: Settings::default(),
         }
@@ -19,9 +19,10 @@ use blackbox_metrics::{
 };
 use eyre::Result;
 use iroh::EndpointId;
-use laminar_stream::config::LayerConfig;
+use laminar_stream::config::{LayerConfig, Reachability};
 use serde_with::serde_as;
 use tauri::{AppHandle, Manager, WebviewWindow, Wry};
+use tokio::sync::watch;
 use tracing_subscriber::{filter::EnvFilter, prelude::*};
 
 use crate::{
@@ -50,6 +51,8 @@ fn get_state(state: tauri::State<'_, AppData>) -> State {
 #[serde(rename_all = "camelCase")]
 struct Status {
     db_size: u64,
+    // `None` until the reader is listening.
+    reachability: Option<Reachability>,
 }
 
 #[tauri::command]
@@ -58,6 +61,7 @@ fn get_status(state: tauri::State<'_, AppData>) -> tauri::Result<Status> {
 
     Ok(Status {
         db_size: meta.len(),
+        reachability: *state.reachability.borrow(),
     })
 }
 
@@ -176,6 +180,7 @@ struct AppData {
     storage: Storage,
     config: RwLock<config::Config>,
     metrics: SamplerHandle<CounterKey, CounterValue>,
+    reachability: watch::Receiver<Option<Reachability>>,
     state: State,
 }
 
@@ -213,7 +218,11 @@ pub fn run() {
             let reader_config = config.reader.clone();
             let key = reader_config.key.load()?;
 
-            let enable = layer_config.remote != Some(key.public());
+            let enable = layer_config
+                .remote
+                .as_ref()
+                .and_then(laminar_stream::config::Remote::key)
+                != Some(key.public());
             setup_logging(layer_config.clone(), enable)?;
 
             tracing::info!(
@@ -236,10 +245,13 @@ pub fn run() {
 
             tauri::async_runtime::spawn(runner);
 
+            let (reachability_tx, reachability) = watch::channel(None);
+
             app.manage(AppData {
                 storage,
                 config: RwLock::new(config),
                 metrics: sampler,
+                reachability,
                 state: State::new(key.public(), db.clone()),
             });
 
@@ -254,6 +266,7 @@ pub fn run() {
                 .config(reader_config)
                 .handle(app.handle().clone())
                 .key(key)
+                .reachability(reachability_tx)
                 .build();
             db::spawn(db.path, |pool| async move { stream.run(pool).await });
 
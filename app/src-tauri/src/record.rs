@@ -1,5 +1,5 @@
 use laminar_stream::{
-    Claims, Record,
+    Claims, Kind, Record, ResourceSample,
     sink::{DisconnectReason, Identity, ResponseEvent},
 };
 use sqlx::{Pool, Sqlite};
@@ -15,6 +15,9 @@ struct InsertRecordParams<'a> {
     received_ms: i64,
     span_id: Option<i64>,
     parent_id: Option<i64>,
+    trace_id: Option<String>,
+    trace_span_id: Option<String>,
+    trace_parent_id: Option<String>,
     source: Option<&'a str>,
     level: Option<i64>,
     message: &'a str,
@@ -42,8 +45,23 @@ impl<'a> InsertRecordParams<'a> {
                 .as_ref()
                 .and_then(|trace| trace.parent)
                 .map(|value| value as i64),
+            trace_id: body
+                .trace
+                .as_ref()
+                .and_then(|trace| trace.context)
+                .map(|context| context.trace_id_hex()),
+            trace_span_id: body
+                .trace
+                .as_ref()
+                .and_then(|trace| trace.context)
+                .map(|context| context.span_id_hex()),
+            trace_parent_id: body
+                .trace
+                .as_ref()
+                .and_then(|trace| trace.parent_context)
+                .map(|context| context.span_id_hex()),
             source: body.source.as_deref(),
-            level: body.level.clone().map(|current| current as i64),
+            level: body.level.map(|current| current as i64),
             message: body.message.as_str(),
             fields_json: body.fields.as_str(),
         }
@@ -59,12 +77,15 @@ impl<'a> InsertRecordParams<'a> {
               received_ms,
               span_id,
               parent_id,
+              trace_id,
+              trace_span_id,
+              trace_parent_id,
               source,
               level,
               message,
               fields_json
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
             self.identity_pk,
             self.kind,
@@ -72,6 +93,9 @@ impl<'a> InsertRecordParams<'a> {
             self.received_ms,
             self.span_id,
             self.parent_id,
+            self.trace_id,
+            self.trace_span_id,
+            self.trace_parent_id,
             self.source,
             self.level,
             self.message,
@@ -84,6 +108,87 @@ impl<'a> InsertRecordParams<'a> {
     }
 }
 
+// Span close records don't get a row of their own, they fill in the timing
+// for the span's row instead.
+struct CloseSpanParams<'a> {
+    identity_pk: i64,
+    span_id: Option<i64>,
+    end_ms: i64,
+    duration_ns: Option<i64>,
+    busy_ns: Option<i64>,
+    idle_ns: Option<i64>,
+    follows_from_json: Option<String>,
+    fields_json: &'a str,
+}
+
+impl<'a> CloseSpanParams<'a> {
+    fn from_record(identity_pk: i64, body: &'a Record) -> Self {
+        let timing = body.timing.as_ref();
+        let follows_from = body
+            .trace
+            .as_ref()
+            .map(|trace| {
+                trace
+                    .follows_from
+                    .iter()
+                    .map(|context| context.span_id_hex())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|ids| !ids.is_empty());
+
+        Self {
+            identity_pk,
+            span_id: body
+                .trace
+                .as_ref()
+                .and_then(|trace| trace.span)
+                .map(|value| value as i64),
+            end_ms: body.timestamp,
+            duration_ns: timing.map(|timing| timing.duration_ns as i64),
+            busy_ns: timing.map(|timing| timing.busy_ns as i64),
+            idle_ns: timing.map(|timing| timing.idle_ns as i64),
+            follows_from_json: follows_from
+                .map(|ids| serde_json::Value::from(ids).to_string()),
+            fields_json: body.fields.as_str(),
+        }
+    }
+
+    async fn execute(&self, pool: &Pool<Sqlite>) -> sqlx::Result<()> {
+        // Span ids get reused after a span closes, the most recent span with
+        // the id is the one being closed.
+        sqlx::query!(
+            r#"
+            UPDATE records
+            SET end_ms = ?,
+                duration_ns = ?,
+                busy_ns = ?,
+                idle_ns = ?,
+                follows_from_json = ?,
+                fields_json = json_patch(fields_json, ?)
+            WHERE id = (
+              SELECT MAX(id)
+              FROM records
+              WHERE identity_pk = ?
+                AND kind = 1
+                AND span_id = ?
+            )
+            "#,
+            self.end_ms,
+            self.duration_ns,
+            self.busy_ns,
+            self.idle_ns,
+            self.follows_from_json,
+            self.fields_json,
+            self.identity_pk,
+            self.span_id,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
 struct IdentityInsertParams<'a> {
     writer_id: &'a str,
     display_name: Option<&'a str>,
@@ -91,6 +196,7 @@ struct IdentityInsertParams<'a> {
     process_name: Option<&'a str>,
     hostname: &'a str,
     start_ms: Option<i64>,
+    labels_json: String,
 }
 
 impl<'a> IdentityInsertParams<'a> {
@@ -117,14 +223,21 @@ impl<'a> IdentityInsertParams<'a> {
                 .source
                 .as_ref()
                 .map(|source| source.start as i64),
+            labels_json: serde_json::to_string(&identity.assertion.labels)
+                .unwrap_or_else(|_| "{}".to_string()),
         }
     }
 
+    // A writer that reloaded its config handshakes again as the same
+    // identity, the latest display name and labels win.
     async fn execute(&self, pool: &Pool<Sqlite>) -> sqlx::Result<()> {
         sqlx::query!(
             r#"
-                INSERT OR IGNORE INTO identity (writer_id, display_name, pid, process_name, hostname, start_ms)
-                VALUES (?, ?, ?, ?, ?, ?)
+                INSERT INTO identity (writer_id, display_name, pid, process_name, hostname, start_ms, labels_json)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (writer_id, pid, process_name, hostname, start_ms) DO UPDATE SET
+                    display_name = excluded.display_name,
+                    labels_json = excluded.labels_json
             "#,
             self.writer_id,
             self.display_name,
@@ -132,6 +245,7 @@ impl<'a> IdentityInsertParams<'a> {
             self.process_name,
             self.hostname,
             self.start_ms,
+            self.labels_json,
         )
         .execute(pool)
         .await?;
@@ -218,6 +332,14 @@ async fn insert_record_data(
     received_at: i64,
     body: &Record,
 ) -> sqlx::Result<()> {
+    if matches!(body.kind, Kind::SpanClose) {
+        metrics::counter!("db.update", "table" => "records").increment(1);
+
+        return CloseSpanParams::from_record(identity_pk, body)
+            .execute(pool)
+            .await;
+    }
+
     metrics::counter!("db.insert", "table" => "records").increment(1);
 
     InsertRecordParams::from_record(identity_pk, received_at, body)
@@ -227,6 +349,49 @@ async fn insert_record_data(
     Ok(())
 }
 
+async fn insert_resources(
+    pool: &Pool<Sqlite>,
+    session_id: &str,
+    sample: &ResourceSample,
+) -> sqlx::Result<()> {
+    metrics::counter!("db.insert", "table" => "resources").increment(1);
+
+    let pid = i64::from(sample.pid);
+    let cpu_user_ms = sample.cpu_user_ms as i64;
+    let cpu_system_ms = sample.cpu_system_ms as i64;
+    let rss_bytes = sample.rss_bytes as i64;
+    let threads = sample.threads as i64;
+    let open_fds = sample.open_fds as i64;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO resources (
+          session_id,
+          ts_ms,
+          pid,
+          cpu_user_ms,
+          cpu_system_ms,
+          rss_bytes,
+          threads,
+          open_fds
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        session_id,
+        sample.timestamp,
+        pid,
+        cpu_user_ms,
+        cpu_system_ms,
+        rss_bytes,
+        threads,
+        open_fds,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 async fn upsert_connected_session(
     pool: &Pool<Sqlite>,
     session_id: &str,
@@ -311,9 +476,16 @@ impl WithSql for laminar_stream::sink::Response<Claims, Record> {
         )
         .await?;
 
-        if let ResponseEvent::Data(body) = &self.event {
-            insert_record_data(pool, identity_pk, self.received_at, body)
-                .await?;
+        match &self.event {
+            ResponseEvent::Data(body) => {
+                insert_record_data(pool, identity_pk, self.received_at, body)
+                    .await?;
+            }
+            ResponseEvent::Resources(sample) => {
+                insert_resources(pool, &self.session_id.to_string(), sample)
+                    .await?;
+            }
+            _ => {}
         }
 
         Ok(())
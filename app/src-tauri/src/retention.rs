@@ -49,7 +49,23 @@ impl RetentionTask {
             .await?
             .rows_affected();
 
-            tracing::info!(deleted, cutoff, "retention cleanup");
+            let deleted_resources = sqlx::query!(
+                r#"
+                DELETE FROM resources
+                WHERE ts_ms < ?
+                "#,
+                cutoff,
+            )
+            .execute(&pool)
+            .await?
+            .rows_affected();
+
+            tracing::info!(
+                deleted,
+                deleted_resources,
+                cutoff,
+                "retention cleanup"
+            );
 
             interval.tick().await;
         }
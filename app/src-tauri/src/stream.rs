@@ -1,9 +1,15 @@
+use std::time::Duration;
+
 use eyre::Result;
 use futures::StreamExt;
 use iroh::SecretKey;
-use laminar_stream::{Reader, config::ReaderConfig};
+use laminar_stream::{
+    Reader,
+    config::{Reachability, ReaderConfig},
+};
 use sqlx::{Pool, Sqlite};
 use tauri::{AppHandle, Emitter};
+use tokio::{sync::watch, time};
 
 use crate::{
     ON_EVENT,
@@ -13,12 +19,16 @@ use crate::{
 
 pub const MESSAGE_RECEIVED: &str = "message.received";
 
+// A relay can connect (or go away) at any point after the reader started.
+const REACHABILITY_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(bon::Builder)]
 pub struct RecordStream {
     handle: AppHandle,
 
     config: ReaderConfig,
     key: SecretKey,
+    reachability: watch::Sender<Option<Reachability>>,
 }
 
 impl RecordStream {
@@ -36,6 +46,9 @@ impl RecordStream {
             .await?;
         let mut debounce = Debounce::default();
 
+        let mut reachability = time::interval(REACHABILITY_INTERVAL);
+        reachability.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
         loop {
             tokio::select! {
                 Some(msg) = reader.next() => {
@@ -48,6 +61,14 @@ impl RecordStream {
                 _ = debounce.ready() => {
                     self.handle.emit(ON_EVENT, ())?;
                 }
+                _ = reachability.tick() => {
+                    let current = Some(reader.reachability());
+                    self.reachability.send_if_modified(|previous| {
+                        let changed = *previous != current;
+                        *previous = current;
+                        changed
+                    });
+                }
             }
         }
     }
@@ -25,6 +25,7 @@ import { routes } from '@/routes'
 import {
   ingestAtom,
   type IngestPoint,
+  type Reachability,
   type SessionRow,
   sessionsAtom,
   statusAtom,
@@ -88,6 +89,25 @@ const Sessions = ({ rows, total }: { rows: SessionRow[]; total: number }) => (
   </>
 )
 
+const REACHABILITY = {
+  local: 'Local network only',
+  direct: 'Direct, no relay',
+  relay: 'Relay',
+} satisfies Record<Reachability, string>
+
+const Network = () => {
+  const { reachability } = useAtomValue(statusAtom)
+
+  return (
+    <div className="mt-3 border-t pt-2">
+      <div className="flex items-center justify-between text-xs text-muted-foreground">
+        <span>Network</span>
+        <span>{reachability ? REACHABILITY[reachability] : 'starting'}</span>
+      </div>
+    </div>
+  )
+}
+
 const Storage = () => {
   const { dbSize } = useAtomValue(statusAtom)
 
@@ -351,6 +371,7 @@ export const Status = () => {
           <Sessions rows={allClients} total={totalSessions} />
 
           <Ingest />
+          <Network />
           <Storage />
         </div>
       </SheetContent>
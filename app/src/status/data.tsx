@@ -24,8 +24,13 @@ export interface SessionsResult {
   total: number
 }
 
+// How writers can reach the reader.
+export type Reachability = 'local' | 'direct' | 'relay'
+
 export interface Status {
   dbSize: number
+  // `null` until the reader is listening.
+  reachability: Reachability | null
 }
 
 export interface IngestPoint {
@@ -57,6 +62,7 @@ export const statusAtom = unwrap(
   prev =>
     prev ?? {
       dbSize: 0,
+      reachability: null,
     },
 )
 statusAtom.debugLabel = 'statusAtom'
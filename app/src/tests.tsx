@@ -67,7 +67,9 @@ export const dispatchInvoke =
         return stub.state ? resolveValue(stub.state, cmd, args) : DEFAULT_STATE
       }
       case INVOKE_STATUS: {
-        return stub.status ? resolveValue(stub.status, cmd, args) : { dbSize: 0 }
+        return stub.status
+          ? resolveValue(stub.status, cmd, args)
+          : { dbSize: 0, reachability: null }
       }
       case INVOKE_SERIES: {
         return stub.series
@@ -21,6 +21,7 @@ export interface _SqlxMigrations {
 export interface Identity {
   display_name: string | null;
   hostname: string;
+  labels_json: Generated<string>;
   pid: number | null;
   pk: Generated<number>;
   process_name: string | null;
@@ -29,9 +30,14 @@ export interface Identity {
 }
 
 export interface Records {
+  busy_ns: number | null;
+  duration_ns: number | null;
+  end_ms: number | null;
   fields_json: string;
+  follows_from_json: string | null;
   id: Generated<number>;
   identity_pk: number;
+  idle_ns: number | null;
   kind: number;
   level: number | null;
   marker_kind: number | null;
@@ -41,6 +47,21 @@ export interface Records {
   received_ms: number;
   source: string | null;
   span_id: number | null;
+  trace_id: string | null;
+  trace_parent_id: string | null;
+  trace_span_id: string | null;
+  ts_ms: number;
+}
+
+export interface Resources {
+  cpu_system_ms: number;
+  cpu_user_ms: number;
+  id: Generated<number>;
+  open_fds: number;
+  pid: number;
+  rss_bytes: number;
+  session_id: string;
+  threads: number;
   ts_ms: number;
 }
 
@@ -57,5 +78,6 @@ export interface DB {
   _sqlx_migrations: _SqlxMigrations;
   identity: Identity;
   records: Records;
+  resources: Resources;
   sessions: Sessions;
 }
@@ -0,0 +1,73 @@
+use clap::{Parser, Subcommand};
+use eyre::Result;
+use laminar_stream::Config;
+use serde_json::Value;
+
+#[derive(Parser, Debug)]
+#[command(name = "config", about = "Inspect the merged configuration")]
+pub struct Args {
+    #[arg(skip)]
+    pub config: Config,
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    // Print every key that is set, along with where it came from.
+    Show,
+    // Check that writers and readers can start with this config.
+    Check,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    match args.command {
+        ConfigCommand::Show => {
+            eprintln!(
+                "# {} (profile: {})",
+                args.config.path().display(),
+                args.config.profile()
+            );
+
+            let mut keys = Vec::new();
+            flatten(
+                String::new(),
+                serde_json::to_value(&args.config)?,
+                &mut keys,
+            );
+
+            let provenance = args.config.provenance();
+            for (key, value) in keys {
+                match provenance.of(&key) {
+                    Some(source) => println!("{key} = {value}  # {source}"),
+                    None => println!("{key} = {value}"),
+                }
+            }
+        }
+        ConfigCommand::Check => {
+            args.config.check()?;
+
+            println!("{} is valid", args.config.path().display());
+        }
+    }
+
+    Ok(())
+}
+
+// Tables are walked down to their values, unset options are skipped.
+fn flatten(prefix: String, value: Value, keys: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Null => {}
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(key, value, keys);
+            }
+        }
+        value => keys.push((prefix, value)),
+    }
+}
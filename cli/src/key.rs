@@ -0,0 +1,160 @@
+use std::{io::Read, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+use eyre::{Result, bail, eyre};
+use laminar_stream::{Config, config::KeySource};
+use qrcode::{QrCode, render::unicode};
+
+#[derive(Parser, Debug)]
+#[command(name = "key", about = "Manage the reader's and writers' keys")]
+pub struct Args {
+    #[arg(skip)]
+    pub config: Config,
+    // Use the key of the writer for this process name instead of the
+    // reader's.
+    #[arg(long, value_name = "NAME", global = true)]
+    writer: Option<String>,
+    #[command(subcommand)]
+    command: KeyCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum KeyCommand {
+    // Print the address, which is what writers need as `remote`.
+    Show {
+        #[arg(long)]
+        qr: bool,
+    },
+    // Create a key file, refuses to replace an existing one.
+    Generate {
+        #[arg(long)]
+        force: bool,
+    },
+    // Replace the key. The reader keeps accepting the old address for a
+    // week, so writers can be updated in the meantime.
+    Rotate,
+    // Print the address, or the secret key to move it to another machine.
+    Export {
+        #[arg(long)]
+        secret: bool,
+    },
+    // Store a secret key printed by `export --secret`, read from stdin when
+    // not given.
+    Import {
+        key: Option<String>,
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+impl Args {
+    fn source(&self) -> KeySource {
+        if self.writer.is_some() {
+            self.config.layer().key
+        } else {
+            self.config.reader().key
+        }
+    }
+
+    fn default_path(&self) -> String {
+        self.writer.as_deref().map_or_else(
+            || KeySource::READER_PATH.to_string(),
+            KeySource::writer_path,
+        )
+    }
+
+    fn file(&self) -> Result<PathBuf> {
+        let source = self.source();
+
+        source
+            .file(&self.default_path())
+            .ok_or_else(|| eyre!("key isn't stored in a file: {source:?}"))
+    }
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    // Key files with loose permissions are only reported as warnings.
+    crate::init_logging();
+
+    match &args.command {
+        KeyCommand::Show { qr } => {
+            let address = args
+                .source()
+                .load_existing(&args.default_path())?
+                .public()
+                .to_string();
+
+            if *qr {
+                let code = QrCode::new(&address)?;
+                println!(
+                    "{}",
+                    code.render::<unicode::Dense1x2>().quiet_zone(true).build()
+                );
+            }
+
+            println!("{address}");
+            if let Ok(file) = args.file() {
+                eprintln!("key file: {}", file.display());
+            }
+        }
+        KeyCommand::Generate { force } => {
+            let file = args.file()?;
+            if !force && file.exists() {
+                bail!(
+                    "{} already exists, use --force to replace it or `rotate`",
+                    file.display()
+                );
+            }
+
+            let key = KeySource::generate();
+            KeySource::write(&file, &key)?;
+
+            println!("{}", key.public());
+        }
+        KeyCommand::Rotate => {
+            let file = args.file()?;
+            let key = KeySource::rotate(&file)?;
+
+            println!("{}", key.public());
+            if args.writer.is_none() {
+                eprintln!(
+                    "the previous address is accepted for another {} days",
+                    KeySource::ROTATION_GRACE.as_secs() / (24 * 60 * 60)
+                );
+            }
+        }
+        KeyCommand::Export { secret } => {
+            let key = args.source().load_existing(&args.default_path())?;
+
+            if *secret {
+                println!("{}", KeySource::encode(&key));
+            } else {
+                println!("{}", key.public());
+            }
+        }
+        KeyCommand::Import { key, force } => {
+            let raw = if let Some(key) = key {
+                key.clone()
+            } else {
+                let mut raw = String::new();
+                std::io::stdin().read_to_string(&mut raw)?;
+                raw
+            };
+            let key = KeySource::decode(&raw)?;
+
+            let file = args.file()?;
+            if !force && file.exists() {
+                bail!(
+                    "{} already exists, use --force to replace it",
+                    file.display()
+                );
+            }
+
+            KeySource::write(&file, &key)?;
+
+            println!("{}", key.public());
+        }
+    }
+
+    Ok(())
+}
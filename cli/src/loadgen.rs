@@ -13,11 +13,14 @@ use tokio::time;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{EnvFilter, prelude::*};
 
+// Matches the terminal defaults when `LayerConfig::filter` is unset.
+const DEFAULT_FILTER: &str = "info,loadgen=trace";
+
 #[derive(Parser, Debug)]
 #[command(name = "loadgen", about = "Load generator CLI")]
 pub struct Args {
-    #[arg(from_global)]
-    config: Config,
+    #[arg(skip)]
+    pub config: Config,
     #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
     emit: bool,
     #[arg(long, value_enum, default_value_t = OutputFormat::Pretty)]
@@ -57,8 +60,14 @@ pub async fn run(args: Args) -> Result<()> {
     }
 
     let (layer, writer) = if args.emit {
-        let (layer, writer) =
-            StreamLayer::builder().config(args.config.layer()).build()?;
+        // The terminal filter only applies to the terminal, the stream layer
+        // has its own.
+        let mut config = args.config.layer();
+        config
+            .filter
+            .get_or_insert_with(|| DEFAULT_FILTER.to_string());
+
+        let (layer, writer) = StreamLayer::builder().config(config).build()?;
         (Some(layer), Some(writer))
     } else {
         (None, None)
@@ -81,8 +90,7 @@ pub async fn run(args: Args) -> Result<()> {
     };
 
     tracing_subscriber::registry()
-        .with(env_filter)
-        .with(fmt_layer)
+        .with(fmt_layer.with_filter(env_filter))
         .with(layer)
         .init();
 
@@ -1,40 +1,27 @@
 #![allow(unreachable_pub)]
 
+mod config;
+mod key;
 mod loadgen;
+mod peers;
 mod sink;
 mod tap;
 
 use clap::{Parser, Subcommand};
-use eyre::Result;
+use eyre::{Result, WrapErr};
 use laminar_stream::Config;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{EnvFilter, prelude::*};
 
-type BoxError = Box<dyn std::error::Error + Send + Sync>;
-
-const CONFIG_DEFAULT_SENTINEL: &str = "__default__";
-
-fn parse_config(raw: &str) -> Result<Config, BoxError> {
-    let cfg = if raw == CONFIG_DEFAULT_SENTINEL {
-        Config::load()?
-    } else {
-        Config::load_from_path(raw)?
-    };
-
-    Ok(cfg)
-}
-
 #[derive(Parser, Debug)]
 #[command(name = "cli", about = "laminar-stream CLI")]
 struct Cli {
-    #[arg(
-        long,
-        value_name = "PATH",
-        global = true,
-        default_value = CONFIG_DEFAULT_SENTINEL,
-        value_parser = parse_config
-    )]
-    config: Config,
+    // Defaults to `LAMINAR_CONFIG` or `~/.config/laminar/config.toml`.
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<String>,
+    // A table of the config file merged over the rest, eg `[profiles.work]`.
+    #[arg(long, env = "LAMINAR_PROFILE", global = true)]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Command,
 }
@@ -42,6 +29,9 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Command {
     Tap(tap::Args),
+    Key(key::Args),
+    Peers(peers::Args),
+    Config(config::Args),
     Loadgen(loadgen::Args),
     Sink(sink::Args),
 }
@@ -64,10 +54,35 @@ async fn main() -> Result<()> {
     color_eyre::install()?;
 
     let cli = Cli::parse();
+    // Loaded after parsing so that `--profile` applies wherever it is given.
+    let config =
+        Config::load_with(cli.config.as_deref(), cli.profile.as_deref())
+            .wrap_err("unable to load config")?;
 
     match cli.command {
-        Command::Tap(args) => tap::run(args).await,
-        Command::Loadgen(args) => loadgen::run(args).await,
-        Command::Sink(args) => sink::run(args).await,
+        Command::Tap(mut args) => {
+            args.config = config;
+            tap::run(args).await
+        }
+        Command::Key(mut args) => {
+            args.config = config;
+            key::run(&args)
+        }
+        Command::Peers(mut args) => {
+            args.config = config;
+            peers::run(args)
+        }
+        Command::Config(mut args) => {
+            args.config = config;
+            config::run(&args)
+        }
+        Command::Loadgen(mut args) => {
+            args.config = config;
+            loadgen::run(args).await
+        }
+        Command::Sink(mut args) => {
+            args.config = config;
+            sink::run(args).await
+        }
     }
 }
@@ -0,0 +1,172 @@
+use std::{net::SocketAddr, path::Path};
+
+use clap::{Parser, Subcommand};
+use eyre::{Result, WrapErr, bail, eyre};
+use laminar_stream::{
+    Config,
+    config::{Peer, Remote},
+};
+use toml_edit::{Array, DocumentMut, Item, Table, value};
+
+#[derive(Parser, Debug)]
+#[command(name = "peers", about = "Manage the address book of named sinks")]
+pub struct Args {
+    #[arg(skip)]
+    pub config: Config,
+    #[command(subcommand)]
+    command: PeersCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum PeersCommand {
+    // Add a peer, or replace it with `--force`.
+    Add {
+        name: String,
+        // The peer's address, see `laminar key show`.
+        key: String,
+        #[arg(long = "addr", value_name = "IP:PORT")]
+        addrs: Vec<SocketAddr>,
+        #[arg(long = "relay", value_name = "URL")]
+        relays: Vec<String>,
+        #[arg(long)]
+        force: bool,
+    },
+    List,
+    Remove {
+        name: String,
+    },
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let path = args.config.path();
+
+    match args.command {
+        PeersCommand::Add {
+            name,
+            key,
+            addrs,
+            relays,
+            force,
+        } => {
+            if !force && args.config.peers().contains_key(&name) {
+                bail!("{name} already exists, use --force to replace it");
+            }
+
+            let peer = Peer::builder()
+                .key(key.parse()?)
+                .addrs(addrs)
+                .relays(
+                    relays
+                        .iter()
+                        .map(|relay| relay.parse())
+                        .collect::<Result<_, _>>()?,
+                )
+                .build();
+
+            edit(&path, |doc| {
+                peers_table(doc)?.insert(&name, Item::Table(to_table(&peer)));
+                Ok(())
+            })?;
+        }
+        PeersCommand::List => {
+            let current = args.config.layer().remote;
+
+            for (name, peer) in args.config.peers() {
+                let marker = match &current {
+                    Some(Remote::Peer { name: remote, .. })
+                        if remote == name =>
+                    {
+                        "*"
+                    }
+                    _ => " ",
+                };
+
+                println!("{marker} {name}\t{}", peer.key);
+                for addr in &peer.addrs {
+                    println!("    addr  {addr}");
+                }
+                for relay in &peer.relays {
+                    println!("    relay {relay}");
+                }
+            }
+        }
+        PeersCommand::Remove { name } => {
+            if !args.config.peers().contains_key(&name) {
+                bail!("no peer named {name}");
+            }
+
+            edit(&path, |doc| {
+                peers_table(doc)?.remove(&name);
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn to_table(peer: &Peer) -> Table {
+    let mut table = Table::new();
+    table.insert("key", value(peer.key.to_string()));
+
+    if !peer.addrs.is_empty() {
+        table.insert(
+            "addrs",
+            value(
+                peer.addrs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Array>(),
+            ),
+        );
+    }
+
+    if !peer.relays.is_empty() {
+        table.insert(
+            "relays",
+            value(
+                peer.relays
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Array>(),
+            ),
+        );
+    }
+
+    table
+}
+
+fn peers_table(doc: &mut DocumentMut) -> Result<&mut Table> {
+    doc.entry("peers")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut()
+        .ok_or_else(|| eyre!("`peers` has to be a `[peers]` table"))
+}
+
+// Edits the config file in place, comments and formatting are kept.
+fn edit(
+    path: &Path,
+    f: impl FnOnce(&mut DocumentMut) -> Result<()>,
+) -> Result<()> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .wrap_err_with(|| format!("unable to parse {}", path.display()))?;
+    f(&mut doc)?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, doc.to_string())?;
+
+    Ok(())
+}
@@ -1,13 +1,16 @@
 use clap::Parser;
 use eyre::Result;
 use futures::StreamExt;
-use laminar_stream::{Config, Reader, sink::ResponseEventKind};
+use laminar_stream::{
+    Config, Reader,
+    sink::{ResponseEvent, ResponseEventKind},
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "sink", about = "Run sink server and print received records")]
 pub struct Args {
-    #[arg(from_global)]
-    config: Config,
+    #[arg(skip)]
+    pub config: Config,
 }
 
 pub async fn run(args: Args) -> Result<()> {
@@ -19,11 +22,30 @@ pub async fn run(args: Args) -> Result<()> {
         .await?;
     let mut record_count: u64 = 0;
 
-    tracing::info!(address = %reader.address(), "sink listening");
+    let address = reader.address();
+    tracing::info!(
+        %address,
+        name = args.config.peer_name(&address),
+        reachability = %reader.reachability(),
+        "sink listening"
+    );
 
     while let Some(response) = reader.next().await {
         record_count += 1;
+
+        // Writers that are in the address book are shown by name.
+        let observed = &response.identity.observed;
+        let writer = args
+            .config
+            .peer_name(observed)
+            .map_or_else(|| observed.to_string(), str::to_string);
+
+        if matches!(response.event, ResponseEvent::Connect) {
+            tracing::info!(%writer, "writer connected");
+        }
+
         tracing::debug!(
+            %writer,
             response = ?ResponseEventKind::from(&response.event),
             "record_count={record_count}"
         );
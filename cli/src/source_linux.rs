@@ -0,0 +1,53 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use eyre::Result;
+use laminar_stream::SourceProcess;
+
+use super::Error;
+
+// Pipes show up as `pipe:[inode]` links in `/proc/<pid>/fd`. Any other process
+// holding the same pipe as our stdin is a candidate for the source.
+fn has_pipe(pid: u32, pipe: &PathBuf) -> bool {
+    std::fs::read_dir(format!("/proc/{pid}/fd"))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link == *pipe))
+}
+
+pub(super) fn get_sources() -> Result<Option<SourceProcess>, Error> {
+    let pid = std::process::id();
+    let reader = std::fs::read_link("/proc/self/fd/0")
+        .map_err(|e| Error::NoSource(e.to_string()))?;
+
+    if !reader.to_string_lossy().starts_with("pipe:") {
+        tracing::error!("stdin is not a pipe, no source process candidates");
+        return Ok(None);
+    }
+
+    let sources = std::fs::read_dir("/proc")
+        .map_err(|e| Error::NoSource(e.to_string()))?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|p| *p != pid)
+        .filter(|p| has_pipe(*p, &reader))
+        .map(SourceProcess::try_from)
+        .collect::<Result<HashSet<_>, String>>()
+        .map_err(Error::NoSource)?
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    if sources.is_empty() {
+        tracing::error!("no source process candidates found");
+        return Ok(None);
+    }
+
+    if sources.len() > 1 {
+        tracing::warn!(
+            count = sources.len(),
+            "multiple source process candidates found"
+        );
+    }
+
+    Ok(sources.into_iter().next())
+}
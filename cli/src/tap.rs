@@ -1,8 +1,11 @@
 mod parser;
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 #[path = "source.rs"]
 mod source;
+#[cfg(target_os = "linux")]
+#[path = "source_linux.rs"]
+mod source;
 #[cfg(target_os = "macos")]
 #[path = "source_macos.rs"]
 mod source;
@@ -10,10 +13,7 @@ mod source;
 use clap::ValueEnum;
 use eyre::Result;
 use futures::{Stream, TryStreamExt, pin_mut, stream};
-use laminar_stream::{
-    Config, SourceProcess, Writer,
-    sink::{EmitterOpts, emitter},
-};
+use laminar_stream::{Client, Config, SourceProcess};
 use parser::Parser;
 use tokio::io::{
     self, AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader,
@@ -28,12 +28,30 @@ pub enum Error {
 #[derive(clap::Parser, Debug)]
 #[command(name = "tap", about = "Forward stdin to deck")]
 pub struct Args {
-    #[arg(from_global)]
-    config: Config,
+    #[arg(skip)]
+    pub config: Config,
     #[arg(long, value_enum, default_value_t = Format::Auto)]
     format: Format,
     #[arg(long, value_name = "SOURCE")]
     source: Option<String>,
+    // Send to this peer, or public key, instead of the configured remote.
+    #[arg(long, value_name = "PEER")]
+    to: Option<String>,
+    // Added to the writer's labels, can be repeated.
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+}
+
+fn parse_label(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{raw}`"))?;
+
+    if key.is_empty() {
+        return Err(format!("missing key in `{raw}`"));
+    }
+
+    Ok((key.to_string(), value.to_string()))
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
@@ -52,14 +70,20 @@ pub async fn run(args: Args) -> Result<()> {
     };
     let name = process.clone().map(|p| p.name);
 
-    let (emitter, rx) = emitter(EmitterOpts::default().buffer_size);
+    let mut config = args.config.layer();
+    config.labels.extend(args.labels);
+    if let Some(to) = &args.to {
+        config.remote = Some(args.config.resolve(to)?);
+    }
+
+    if let Some(remote) = &config.remote {
+        tracing::info!(%remote, "sending to");
+    }
 
-    let source = Writer::builder()
-        .rx(rx)
-        .config(args.config.layer())
-        .maybe_source(process.clone())
+    let client = Client::connect()
+        .config(config)
+        .maybe_source(process)
         .build()
-        .run()
         .await?;
     let conv = Parser::new(args.format, name);
 
@@ -69,14 +93,14 @@ pub async fn run(args: Args) -> Result<()> {
     while let Some(line) = lines.try_next().await? {
         let record = conv.to_record(&line)?;
 
-        if emitter.send(record).is_err() {
+        if client.send(record).is_err() {
             tracing::warn!("writer channel closed, stopping tap");
             break;
         }
     }
 
-    drop(emitter);
-    source.await?;
+    // Dropping the client flushes, without blocking the runtime.
+    tokio::task::spawn_blocking(move || drop(client)).await?;
 
     Ok(())
 }
@@ -1,6 +1,12 @@
+mod labels;
+#[cfg(target_os = "linux")]
+mod procfs;
 mod record;
+mod resources;
+mod trace;
 
 use std::{
+    collections::BTreeMap,
     str::FromStr,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -10,8 +16,26 @@ use iroh::PublicKey;
 use libproc::{bsd_info::BSDInfo, proc_pid};
 use serde::{Deserialize, Serialize};
 use strum::FromRepr;
+use tracing_subscriber::field::RecordFields;
 
-pub use crate::api::record::Record;
+pub use crate::api::{
+    labels::{LabelSource, detect_labels},
+    record::Record,
+    resources::ResourceSample,
+    trace::SpanContext,
+};
+
+// Shared with the layer, which needs to visit fields recorded on spans.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) type JsonFields = serde_json::Map<String, serde_json::Value>;
+
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn visit_fields(values: &impl RecordFields) -> JsonFields {
+    let mut visitor = record::FieldVisitor::default();
+    values.record(&mut visitor);
+
+    visitor.raw
+}
 
 #[must_use]
 pub fn now() -> i64 {
@@ -27,16 +51,37 @@ pub fn now() -> i64 {
 pub enum Kind {
     Event = 0,
     Span = 1,
+    // Sent when a span closes, carries `SpanTiming` and any fields that were
+    // recorded after the span was created.
+    SpanClose = 2,
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Deserialize, Serialize, FromRepr)]
+// The lowercase aliases are for config files, the wire format is unaffected.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Deserialize,
+    Serialize,
+    FromRepr,
+)]
 pub enum Level {
+    #[serde(alias = "trace")]
     Trace = 0,
+    #[serde(alias = "debug")]
     Debug = 1,
+    #[serde(alias = "info")]
     Info = 2,
+    #[serde(alias = "warn")]
     Warn = 3,
+    #[serde(alias = "error")]
     Error = 4,
+    #[serde(alias = "off")]
     Off = 5,
 }
 
@@ -52,6 +97,19 @@ impl From<&tracing::Level> for Level {
     }
 }
 
+#[cfg(feature = "log")]
+impl From<log::Level> for Level {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Trace => Self::Trace,
+            log::Level::Debug => Self::Debug,
+            log::Level::Info => Self::Info,
+            log::Level::Warn => Self::Warn,
+            log::Level::Error => Self::Error,
+        }
+    }
+}
+
 impl FromStr for Level {
     type Err = ();
 
@@ -148,10 +206,26 @@ impl TryFrom<u32> for SourceProcess {
     }
 }
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(target_os = "linux")]
+impl TryFrom<u32> for SourceProcess {
+    type Error = String;
+
+    fn try_from(pid: u32) -> Result<Self, Self::Error> {
+        let stat = procfs::Stat::read(pid).map_err(|e| e.to_string())?;
+        let boot = procfs::boot_time_ms().map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            pid,
+            name: procfs::comm(pid).map_err(|e| e.to_string())?,
+            start: boot + procfs::ticks_to_ms(stat.starttime),
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 impl From<u32> for SourceProcess {
     fn from(pid: u32) -> Self {
-        SourceProcess {
+        Self {
             pid,
             name: "unknown".to_string(),
             start: 0,
@@ -172,12 +246,61 @@ pub struct Claims {
     pub hostname: String,
     pub display_name: Option<String>,
     pub source: Option<SourceProcess>,
+    // Arbitrary key-value pairs, eg the branch or CI job a writer belongs to.
+    #[builder(default)]
+    pub labels: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TraceId {
+    // Process local ids, these get reused once a span closes.
     pub span: Option<u64>,
     pub parent: Option<u64>,
+    // Globally unique ids. For events, `context` is the span that the event
+    // happened in.
+    pub context: Option<SpanContext>,
+    pub parent_context: Option<SpanContext>,
+    pub follows_from: Vec<SpanContext>,
+}
+
+// One of the spans an event happened in, see `Record::from_event`.
+#[derive(Debug, Clone)]
+pub struct SpanScope {
+    pub id: u64,
+    pub name: &'static str,
+    pub context: Option<SpanContext>,
+    // Only available when `LayerConfig::span_fields` is enabled.
+    pub fields: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl SpanScope {
+    fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+        });
+
+        if let Some(context) = self.context {
+            value["trace_id"] = context.trace_id_hex().into();
+            value["span_id"] = context.span_id_hex().into();
+        }
+
+        if let Some(fields) = &self.fields {
+            value["fields"] = fields.clone().into();
+        }
+
+        value
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpanTiming {
+    // Wall clock time that the span was created, ms since epoch.
+    pub start: i64,
+    pub duration_ns: u64,
+    // Time spent inside the span (entered) vs outside of it.
+    pub busy_ns: u64,
+    pub idle_ns: u64,
 }
 
 #[derive(Debug, bon::Builder)]
@@ -0,0 +1,265 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::SourceProcess;
+
+// Labels that can be filled in automatically, see `LayerConfig::detect_labels`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelSource {
+    // `cwd`, the working directory of the writer (or the tapped process).
+    Cwd,
+    // `cmdline`, the arguments the writer (or the tapped process) was started
+    // with. These can contain secrets, which is why nothing is detected by
+    // default.
+    Cmdline,
+    // `git.branch`, `git.commit` and `git.worktree` of the repository the
+    // working directory is in.
+    Git,
+    // `ci.*` from the environment variables of common CI providers.
+    Ci,
+}
+
+struct Provider {
+    name: &'static str,
+    // Only set when running on this provider.
+    marker: &'static str,
+    // The variable for each of the `ci.*` labels.
+    vars: &'static [(&'static str, &'static str)],
+}
+
+const CI: &[Provider] = &[
+    Provider {
+        name: "github",
+        marker: "GITHUB_ACTIONS",
+        vars: &[
+            ("repository", "GITHUB_REPOSITORY"),
+            ("pipeline", "GITHUB_WORKFLOW"),
+            ("run", "GITHUB_RUN_ID"),
+            ("job", "GITHUB_JOB"),
+            ("branch", "GITHUB_REF_NAME"),
+            ("commit", "GITHUB_SHA"),
+        ],
+    },
+    Provider {
+        name: "gitlab",
+        marker: "GITLAB_CI",
+        vars: &[
+            ("repository", "CI_PROJECT_PATH"),
+            ("pipeline", "CI_PIPELINE_ID"),
+            ("run", "CI_JOB_ID"),
+            ("job", "CI_JOB_NAME"),
+            ("branch", "CI_COMMIT_REF_NAME"),
+            ("commit", "CI_COMMIT_SHA"),
+        ],
+    },
+    Provider {
+        name: "buildkite",
+        marker: "BUILDKITE",
+        vars: &[
+            ("repository", "BUILDKITE_REPO"),
+            ("pipeline", "BUILDKITE_PIPELINE_SLUG"),
+            ("run", "BUILDKITE_BUILD_NUMBER"),
+            ("job", "BUILDKITE_JOB_ID"),
+            ("branch", "BUILDKITE_BRANCH"),
+            ("commit", "BUILDKITE_COMMIT"),
+        ],
+    },
+    Provider {
+        name: "circleci",
+        marker: "CIRCLECI",
+        vars: &[
+            ("repository", "CIRCLE_PROJECT_REPONAME"),
+            ("pipeline", "CIRCLE_WORKFLOW_ID"),
+            ("run", "CIRCLE_BUILD_NUM"),
+            ("job", "CIRCLE_JOB"),
+            ("branch", "CIRCLE_BRANCH"),
+            ("commit", "CIRCLE_SHA1"),
+        ],
+    },
+];
+
+// Anything that can't be detected is left out. With a `source`, eg under
+// `laminar tap`, `cwd`, `cmdline` and `git` describe that process instead of
+// the writer. They're read from `/proc`, so only on Linux.
+#[must_use]
+pub fn detect_labels(
+    sources: &[LabelSource],
+    source: Option<&SourceProcess>,
+) -> BTreeMap<String, String> {
+    // Sources that were only given a name (pid 0) are the writer itself.
+    let pid = source
+        .map(|source| source.pid)
+        .filter(|pid| *pid != 0 && *pid != std::process::id());
+    let mut labels = BTreeMap::new();
+
+    for label in sources {
+        match label {
+            LabelSource::Cwd => {
+                if let Some(cwd) = cwd(pid) {
+                    labels.insert("cwd".into(), cwd.display().to_string());
+                }
+            }
+            LabelSource::Cmdline => {
+                if let Some(cmdline) = cmdline(pid) {
+                    labels.insert("cmdline".into(), cmdline);
+                }
+            }
+            LabelSource::Git => {
+                // Another process's repository can only be found through its
+                // working directory.
+                let Some(dir) = cwd(pid) else {
+                    continue;
+                };
+
+                for (key, args) in [
+                    ("git.branch", &["rev-parse", "--abbrev-ref", "HEAD"][..]),
+                    ("git.commit", &["rev-parse", "HEAD"]),
+                    ("git.worktree", &["rev-parse", "--show-toplevel"]),
+                ] {
+                    if let Some(value) = git(&dir, args) {
+                        labels.insert(key.into(), value);
+                    }
+                }
+            }
+            LabelSource::Ci => {
+                labels.extend(ci(|name| std::env::var(name).ok()));
+            }
+        }
+    }
+
+    labels
+}
+
+// `None` is the current process.
+fn cwd(pid: Option<u32>) -> Option<PathBuf> {
+    pid.map_or_else(
+        || std::env::current_dir().ok(),
+        |pid| std::fs::read_link(format!("/proc/{pid}/cwd")).ok(),
+    )
+}
+
+fn cmdline(pid: Option<u32>) -> Option<String> {
+    match pid {
+        None => Some(std::env::args().collect::<Vec<_>>().join(" ")),
+        Some(pid) => {
+            // The arguments are NUL terminated. It's empty for a process
+            // that is still starting up, or a zombie.
+            let raw = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+            let cmdline = raw
+                .split(|b| *b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            (!cmdline.is_empty()).then_some(cmdline)
+        }
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+fn ci(var: impl Fn(&str) -> Option<String>) -> BTreeMap<String, String> {
+    let Some(provider) = CI.iter().find(|p| var(p.marker).is_some()) else {
+        return BTreeMap::new();
+    };
+
+    std::iter::once(("ci.provider".to_string(), provider.name.to_string()))
+        .chain(provider.vars.iter().filter_map(|(label, name)| {
+            Some((format!("ci.{label}"), var(name)?))
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_ci() {
+        let env = HashMap::from([
+            ("GITLAB_CI", "true"),
+            ("CI_PROJECT_PATH", "laminar/laminar"),
+            ("CI_JOB_NAME", "test"),
+        ]);
+        let labels = ci(|name| env.get(name).map(ToString::to_string));
+
+        assert_eq!(
+            labels,
+            BTreeMap::from([
+                ("ci.provider".to_string(), "gitlab".to_string()),
+                ("ci.repository".to_string(), "laminar/laminar".to_string()),
+                ("ci.job".to_string(), "test".to_string()),
+            ])
+        );
+
+        assert!(ci(|_| None).is_empty());
+    }
+
+    #[test]
+    fn test_detect() {
+        let labels =
+            detect_labels(&[LabelSource::Cwd, LabelSource::Cmdline], None);
+
+        assert!(labels.contains_key("cwd"));
+        assert!(labels.contains_key("cmdline"));
+        assert!(detect_labels(&[], None).is_empty());
+    }
+
+    // Under `laminar tap`, the labels are the tapped process's.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_detect_source() -> std::io::Result<()> {
+        let dir = std::env::temp_dir();
+        let mut child =
+            Command::new("sleep").arg("10").current_dir(&dir).spawn()?;
+        let source = SourceProcess {
+            pid: child.id(),
+            name: "sleep".into(),
+            start: 0,
+        };
+
+        // The child might not have finished exec'ing yet.
+        let mut labels = BTreeMap::new();
+        for _ in 0..100 {
+            labels = detect_labels(
+                &[LabelSource::Cwd, LabelSource::Cmdline],
+                Some(&source),
+            );
+            if labels.contains_key("cmdline") {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        child.kill()?;
+        child.wait()?;
+
+        assert_eq!(
+            labels.get("cwd").map(String::as_str),
+            Some(dir.canonicalize()?.to_string_lossy().as_ref())
+        );
+        assert_eq!(labels.get("cmdline").map(String::as_str), Some("sleep 10"));
+
+        Ok(())
+    }
+}
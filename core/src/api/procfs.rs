@@ -0,0 +1,89 @@
+use std::io::{Error, ErrorKind, Result};
+
+// USER_HZ is part of the userspace ABI and is 100 on every architecture Linux
+// supports, regardless of the kernel's internal tick rate.
+const TICKS_PER_SECOND: u64 = 100;
+
+fn invalid(pid: u32, file: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("unable to parse /proc/{pid}/{file}"),
+    )
+}
+
+pub(super) const fn ticks_to_ms(ticks: u64) -> u64 {
+    ticks * 1_000 / TICKS_PER_SECOND
+}
+
+#[derive(Debug)]
+pub(super) struct Stat {
+    pub(super) utime: u64,
+    pub(super) stime: u64,
+    pub(super) num_threads: u64,
+    pub(super) starttime: u64,
+}
+
+impl Stat {
+    pub(super) fn read(pid: u32) -> Result<Self> {
+        let raw = std::fs::read_to_string(format!("/proc/{pid}/stat"))?;
+
+        // The command name is wrapped in parens and can contain anything,
+        // including spaces and parens. Everything after the last paren is
+        // whitespace separated, starting with field 3 (state).
+        let (_, rest) =
+            raw.rsplit_once(')').ok_or_else(|| invalid(pid, "stat"))?;
+        let fields = rest.split_whitespace().collect::<Vec<_>>();
+
+        let field = |n: usize| {
+            fields
+                .get(n - 3)
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| invalid(pid, "stat"))
+        };
+
+        Ok(Self {
+            utime: field(14)?,
+            stime: field(15)?,
+            num_threads: field(20)?,
+            starttime: field(22)?,
+        })
+    }
+}
+
+pub(super) fn rss_bytes(pid: u32) -> Result<u64> {
+    let raw = std::fs::read_to_string(format!("/proc/{pid}/status"))?;
+
+    raw.lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kb| kb * 1_024)
+        .ok_or_else(|| invalid(pid, "status"))
+}
+
+pub(super) fn open_fds(pid: u32) -> Result<u64> {
+    Ok(std::fs::read_dir(format!("/proc/{pid}/fd"))?.count() as u64)
+}
+
+pub(super) fn comm(pid: u32) -> Result<String> {
+    Ok(std::fs::read_to_string(format!("/proc/{pid}/comm"))?
+        .trim_end()
+        .to_string())
+}
+
+// Boot time in milliseconds since the epoch, used to turn `starttime` (ticks
+// since boot) into an absolute timestamp.
+pub(super) fn boot_time_ms() -> Result<u64> {
+    std::fs::read_to_string("/proc/stat")?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(|secs| secs * 1_000)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing btime"))
+}
@@ -3,11 +3,11 @@ use std::fmt;
 use serde::{Deserialize, Serialize};
 use tracing::field::Visit;
 
-use crate::api::{Kind, Level, TraceId, now};
+use crate::api::{
+    JsonFields, Kind, Level, SpanScope, SpanTiming, TraceId, now,
+};
 
-type JsonFields = serde_json::Map<String, serde_json::Value>;
-
-#[derive(Debug, Deserialize, Serialize, bon::Builder)]
+#[derive(Debug, Clone, Deserialize, Serialize, bon::Builder)]
 pub struct Record {
     #[builder(default = Kind::Event)]
     pub kind: Kind,
@@ -17,6 +17,7 @@ pub struct Record {
     pub source: Option<String>,
     pub message: String,
     pub trace: Option<TraceId>,
+    pub timing: Option<SpanTiming>,
     #[builder(into)]
     pub fields: String,
 }
@@ -30,9 +31,40 @@ impl Record {
         serde_json::from_slice(data)
     }
 
+    // Adds `key` to the `tracing` metadata of an existing record, creating it
+    // for records that didn't come from `tracing`. This re-parses the fields,
+    // keep it off the hot path.
+    #[allow(clippy::redundant_pub_crate)]
+    pub(crate) fn annotate(&mut self, key: &str, value: serde_json::Value) {
+        let Ok(serde_json::Value::Object(mut fields)) =
+            serde_json::from_str(&self.fields)
+        else {
+            return;
+        };
+
+        Self::annotate_fields(&mut fields, key, value);
+        self.fields = serde_json::Value::Object(fields).to_string();
+    }
+
+    // `annotate` for fields that have already been parsed.
+    #[allow(clippy::redundant_pub_crate)]
+    pub(crate) fn annotate_fields(
+        fields: &mut JsonFields,
+        key: &str,
+        value: serde_json::Value,
+    ) {
+        if let serde_json::Value::Object(tracing) = fields
+            .entry("tracing")
+            .or_insert_with(|| serde_json::Value::Object(JsonFields::new()))
+        {
+            tracing.insert(key.to_string(), value);
+        }
+    }
+
+    #[must_use]
     pub fn from_span(
         attrs: &tracing::span::Attributes<'_>,
-        id: &tracing::span::Id,
+        trace: TraceId,
     ) -> Self {
         let mut visitor = FieldVisitor::default();
         attrs.record(&mut visitor);
@@ -44,17 +76,15 @@ impl Record {
             .kind(Kind::Span)
             .level(attrs.metadata().level().into())
             .source(attrs.metadata().target().to_string())
-            .trace(TraceId {
-                span: Some(id.into_u64()),
-                parent: attrs.parent().map(tracing::span::Id::into_u64),
-            })
+            .trace(trace)
             .message(message)
             .fields(serde_json::Value::Object(fields).to_string())
             .build()
     }
 
+    // `scope` is the span the event happened in, followed by its parents.
     #[must_use]
-    pub fn from_event(event: &tracing::Event<'_>) -> Self {
+    pub fn from_event(event: &tracing::Event<'_>, scope: &[SpanScope]) -> Self {
         let mut visitor = FieldVisitor::default();
         event.record(&mut visitor);
         let message = visitor
@@ -63,7 +93,18 @@ impl Record {
             .map(|f| f.as_str().map(str::to_owned).unwrap_or_default())
             .unwrap_or_default();
 
-        let fields = event.metadata().merge_fields(visitor.raw);
+        let mut fields = event.metadata().merge_fields(visitor.raw);
+        if !scope.is_empty()
+            && let Some(serde_json::Value::Object(tracing)) =
+                fields.get_mut("tracing")
+        {
+            tracing.insert(
+                "spans".to_string(),
+                scope.iter().rev().map(SpanScope::to_json).collect(),
+            );
+        }
+
+        let leaf = scope.first();
 
         Self::builder()
             .kind(Kind::Event)
@@ -72,12 +113,85 @@ impl Record {
             .source(event.metadata().target().to_string())
             .trace(TraceId {
                 span: None,
-                parent: event.parent().map(tracing::Id::into_u64),
+                parent: leaf.map(|span| span.id),
+                context: leaf.and_then(|span| span.context),
+                parent_context: None,
+                follows_from: Vec::new(),
             })
             .message(message)
             .fields(serde_json::Value::Object(fields).to_string())
             .build()
     }
+
+    // `fields` only contains values recorded after the span was created, the
+    // rest were sent with the original span.
+    #[must_use]
+    pub fn from_span_close(
+        metadata: &tracing::Metadata<'_>,
+        trace: TraceId,
+        timing: SpanTiming,
+        fields: JsonFields,
+    ) -> Self {
+        Self::builder()
+            .kind(Kind::SpanClose)
+            .level(metadata.level().into())
+            .source(metadata.target().to_string())
+            .trace(trace)
+            .timing(timing)
+            .message(metadata.name().to_string())
+            .fields(serde_json::Value::Object(fields).to_string())
+            .build()
+    }
+
+    #[must_use]
+    pub fn from_panic(info: &std::panic::PanicHookInfo<'_>) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+
+        let location = info.location();
+        let fields = serde_json::json!({
+            "panic": {
+                "file": location.map(std::panic::Location::file),
+                "line": location.map(std::panic::Location::line),
+                "column": location.map(std::panic::Location::column),
+                "thread": std::thread::current().name(),
+                "backtrace":
+                    std::backtrace::Backtrace::force_capture().to_string(),
+            },
+        });
+
+        Self::builder()
+            .kind(Kind::Event)
+            .level(Level::Error)
+            .source("panic".to_string())
+            .message(message)
+            .fields(fields.to_string())
+            .build()
+    }
+
+    // Key-value pairs end up in `fields`, along with the record's metadata
+    // under `log`.
+    #[cfg(feature = "log")]
+    #[must_use]
+    pub fn from_log(record: &log::Record<'_>) -> Self {
+        let mut visitor = KvVisitor::default();
+        // The visitor never fails, errors only come from the source itself.
+        record.key_values().visit(&mut visitor).ok();
+
+        let fields = record.merge_fields(visitor.raw);
+
+        Self::builder()
+            .kind(Kind::Event)
+            .level(record.level().into())
+            .source(record.target().to_string())
+            .message(record.args().to_string())
+            .fields(serde_json::Value::Object(fields).to_string())
+            .build()
+    }
 }
 
 trait MergeFields {
@@ -101,9 +215,95 @@ impl MergeFields for tracing::Metadata<'_> {
     }
 }
 
+#[cfg(feature = "log")]
+impl MergeFields for log::Record<'_> {
+    fn merge_fields(&self, mut source: JsonFields) -> JsonFields {
+        source.insert(
+            "log".to_string(),
+            serde_json::json!({
+                "target": self.target(),
+                "file": self.file(),
+                "line": self.line(),
+                "module_path": self.module_path(),
+            }),
+        );
+
+        source
+    }
+}
+
+#[cfg(feature = "log")]
 #[derive(Default)]
-struct FieldVisitor {
-    raw: serde_json::Map<String, serde_json::Value>,
+struct KvVisitor {
+    raw: JsonFields,
+}
+
+#[cfg(feature = "log")]
+impl<'kvs> log::kv::VisitSource<'kvs> for KvVisitor {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        let mut json = KvValue(serde_json::Value::Null);
+        value.visit(&mut json)?;
+
+        self.raw.insert(key.to_string(), json.0);
+
+        Ok(())
+    }
+}
+
+// Without `kv_serde` there's no way to serialize a value directly, anything
+// that isn't a primitive is formatted instead.
+#[cfg(feature = "log")]
+struct KvValue(serde_json::Value);
+
+#[cfg(feature = "log")]
+impl log::kv::VisitValue<'_> for &mut KvValue {
+    fn visit_any(
+        &mut self,
+        value: log::kv::Value<'_>,
+    ) -> Result<(), log::kv::Error> {
+        self.0 = serde_json::Value::String(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), log::kv::Error> {
+        self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), log::kv::Error> {
+        self.0 = serde_json::Number::from_f64(value)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub(super) struct FieldVisitor {
+    pub(super) raw: JsonFields,
 }
 
 impl Visit for FieldVisitor {
@@ -150,20 +350,200 @@ impl Visit for FieldVisitor {
         field: &tracing::field::Field,
         value: valuable::Value<'_>,
     ) {
-        self.raw.insert(
-            field.name().to_string(),
-            serde_json::Value::String(format!("{value:?}")),
-        );
+        self.raw
+            .insert(field.name().to_string(), valuable_json(value));
     }
 
+    // Errors are recorded as `{ message, type, chain }`. `chain` holds the
+    // message of every `source()`, closest first, which is where eyre and
+    // anyhow keep their context. `type` is left out when it isn't known, see
+    // `error_type`.
     fn record_error(
         &mut self,
         field: &tracing::field::Field,
         value: &(dyn std::error::Error + 'static),
     ) {
-        self.raw.insert(
-            field.name().to_string(),
-            serde_json::Value::String(value.to_string()),
-        );
+        self.raw.insert(field.name().to_string(), error_json(value));
+    }
+}
+
+fn error_json(err: &(dyn std::error::Error + 'static)) -> serde_json::Value {
+    let chain = std::iter::successors(err.source(), |err| err.source())
+        .map(|err| serde_json::Value::String(err.to_string()))
+        .collect::<Vec<_>>();
+
+    let mut error = JsonFields::new();
+    error.insert("message".to_string(), err.to_string().into());
+    if let Some(name) = error_type(err) {
+        error.insert("type".to_string(), name.into());
+    }
+    error.insert("chain".to_string(), chain.into());
+
+    serde_json::Value::Object(error)
+}
+
+// Trait objects don't carry their type name, this only covers the errors that
+// can be identified by downcasting. The errors in an `eyre::Report` or
+// `anyhow::Error` are recorded as the error they wrap, so those are found too.
+fn error_type(err: &(dyn std::error::Error + 'static)) -> Option<&'static str> {
+    macro_rules! downcast {
+        ($($ty:ty),+ $(,)?) => {
+            $(
+                if err.is::<$ty>() {
+                    return Some(std::any::type_name::<$ty>());
+                }
+            )+
+        };
+    }
+
+    downcast!(
+        std::io::Error,
+        std::fmt::Error,
+        std::num::ParseIntError,
+        std::num::ParseFloatError,
+        std::num::TryFromIntError,
+        std::str::ParseBoolError,
+        std::str::Utf8Error,
+        std::string::FromUtf8Error,
+        std::env::VarError,
+        std::net::AddrParseError,
+        std::time::SystemTimeError,
+        serde_json::Error,
+    );
+
+    None
+}
+
+// Walks a `valuable::Value` into JSON. The shape matches what serde_json
+// produces for the same type, so it doesn't matter whether a value was
+// recorded with `valuable` or serialized. Enums are externally tagged,
+// `{ "Variant": fields }`, and unit variants are just the name.
+#[cfg(feature = "valuable")]
+fn valuable_json(value: valuable::Value<'_>) -> serde_json::Value {
+    use valuable::Value;
+
+    let number = |value: f64| {
+        serde_json::Number::from_f64(value)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number)
+    };
+
+    match value {
+        Value::Bool(v) => v.into(),
+        Value::Char(v) => v.to_string().into(),
+        Value::F32(v) => number(v.into()),
+        Value::F64(v) => number(v),
+        Value::I8(v) => v.into(),
+        Value::I16(v) => v.into(),
+        Value::I32(v) => v.into(),
+        Value::I64(v) => v.into(),
+        Value::Isize(v) => v.into(),
+        Value::U8(v) => v.into(),
+        Value::U16(v) => v.into(),
+        Value::U32(v) => v.into(),
+        Value::U64(v) => v.into(),
+        Value::Usize(v) => v.into(),
+        // JSON numbers don't go this high, fall back to a string.
+        Value::I128(v) => {
+            i64::try_from(v).map_or_else(|_| v.to_string().into(), Into::into)
+        }
+        Value::U128(v) => {
+            u64::try_from(v).map_or_else(|_| v.to_string().into(), Into::into)
+        }
+        Value::String(v) => v.into(),
+        Value::Path(v) => v.display().to_string().into(),
+        Value::Error(err) => error_json(err),
+        Value::Listable(v) => ValuableVisitor::walk(v).list.into(),
+        Value::Tuplable(v) => ValuableVisitor::walk(v).list.into(),
+        Value::Mappable(v) => ValuableVisitor::walk(v).map.into(),
+        Value::Structable(v) => {
+            fields_json(v.definition().fields().is_named(), v)
+        }
+        Value::Enumerable(v) => {
+            let variant = v.variant();
+            match fields_json(variant.fields().is_named(), v) {
+                serde_json::Value::Null => variant.name().into(),
+                fields => {
+                    let mut tagged = JsonFields::new();
+                    tagged.insert(variant.name().to_string(), fields);
+                    tagged.into()
+                }
+            }
+        }
+        // `Unit` and anything added to `Value` later on.
+        _ => serde_json::Value::Null,
+    }
+}
+
+// Named fields become an object. Unnamed ones follow serde's newtype and
+// tuple conventions, a single field is unwrapped and no fields is `null`.
+#[cfg(feature = "valuable")]
+fn fields_json(
+    named: bool,
+    value: &(impl valuable::Valuable + ?Sized),
+) -> serde_json::Value {
+    let mut visitor = ValuableVisitor::walk(value);
+
+    if named {
+        return visitor.map.into();
+    }
+
+    match visitor.list.len() {
+        0 => serde_json::Value::Null,
+        1 => visitor.list.swap_remove(0),
+        _ => visitor.list.into(),
+    }
+}
+
+#[cfg(feature = "valuable")]
+#[derive(Default)]
+struct ValuableVisitor {
+    list: Vec<serde_json::Value>,
+    map: JsonFields,
+}
+
+#[cfg(feature = "valuable")]
+impl ValuableVisitor {
+    // `Value` itself can't be visited, it hands itself straight back to
+    // `visit_value`. This has to be called with what's inside of it.
+    fn walk(value: &(impl valuable::Valuable + ?Sized)) -> Self {
+        let mut visitor = Self::default();
+        value.visit(&mut visitor);
+        visitor
+    }
+}
+
+#[cfg(feature = "valuable")]
+impl valuable::Visit for ValuableVisitor {
+    fn visit_value(&mut self, value: valuable::Value<'_>) {
+        self.list.push(valuable_json(value));
+    }
+
+    fn visit_named_fields(&mut self, named: &valuable::NamedValues<'_>) {
+        for (field, value) in named {
+            self.map
+                .insert(field.name().to_string(), valuable_json(*value));
+        }
+    }
+
+    fn visit_unnamed_fields(&mut self, values: &[valuable::Value<'_>]) {
+        self.list.extend(values.iter().copied().map(valuable_json));
+    }
+
+    fn visit_primitive_slice(&mut self, slice: valuable::Slice<'_>) {
+        self.list.extend(slice.iter().map(valuable_json));
+    }
+
+    // JSON keys have to be strings, anything else uses its JSON encoding.
+    fn visit_entry(
+        &mut self,
+        key: valuable::Value<'_>,
+        value: valuable::Value<'_>,
+    ) {
+        let key = match valuable_json(key) {
+            serde_json::Value::String(key) => key,
+            key => key.to_string(),
+        };
+
+        self.map.insert(key, valuable_json(value));
     }
 }
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResourceSample {
+    pub timestamp: i64,
+    pub pid: u32,
+    pub cpu_user_ms: u64,
+    pub cpu_system_ms: u64,
+    pub rss_bytes: u64,
+    pub threads: u64,
+    pub open_fds: u64,
+}
+
+impl ResourceSample {
+    #[cfg(target_os = "linux")]
+    pub fn read(pid: u32) -> std::io::Result<Self> {
+        use crate::api::{now, procfs};
+
+        let stat = procfs::Stat::read(pid)?;
+
+        Ok(Self {
+            timestamp: now(),
+            pid,
+            cpu_user_ms: procfs::ticks_to_ms(stat.utime),
+            cpu_system_ms: procfs::ticks_to_ms(stat.stime),
+            rss_bytes: procfs::rss_bytes(pid)?,
+            threads: stat.num_threads,
+            open_fds: procfs::open_fds(pid)?,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn read(_pid: u32) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "resource sampling requires /proc",
+        ))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_self() {
+        let sample =
+            ResourceSample::read(std::process::id()).expect("can read /proc");
+
+        assert_eq!(sample.pid, std::process::id());
+        assert!(sample.rss_bytes > 0);
+        assert!(sample.threads > 0);
+        assert!(sample.open_fds > 0);
+    }
+
+    #[test]
+    fn test_read_missing() {
+        assert!(ResourceSample::read(u32::MAX).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+// Globally unique identifiers for a span, compatible with W3C trace context.
+// Unlike `tracing::span::Id`, these are random and never reused, so they can
+// be used to link spans across processes.
+//
+// See https://www.w3.org/TR/trace-context/#traceparent-header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+impl SpanContext {
+    const VERSION: &'static str = "00";
+    const SAMPLED: &'static str = "01";
+
+    #[must_use]
+    pub fn root() -> Self {
+        Self {
+            trace_id: random_non_zero(),
+            span_id: random_non_zero(),
+        }
+    }
+
+    #[must_use]
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: random_non_zero(),
+        }
+    }
+
+    #[must_use]
+    pub fn trace_id_hex(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    #[must_use]
+    pub fn span_id_hex(&self) -> String {
+        format!("{:016x}", self.span_id)
+    }
+}
+
+fn random_non_zero<T>() -> T
+where
+    T: Default + PartialEq,
+    rand::distr::StandardUniform: rand::distr::Distribution<T>,
+{
+    loop {
+        let value = rand::random::<T>();
+        if value != T::default() {
+            return value;
+        }
+    }
+}
+
+// Formats as a `traceparent` header value.
+impl fmt::Display for SpanContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}-{}",
+            Self::VERSION,
+            self.trace_id_hex(),
+            self.span_id_hex(),
+            Self::SAMPLED
+        )
+    }
+}
+
+// Parses a `traceparent` header value. Flags are ignored, everything that is
+// received is recorded.
+impl FromStr for SpanContext {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.trim().split('-');
+
+        let (Some(version), Some(trace_id), Some(span_id), Some(flags)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(());
+        };
+
+        // Future versions may append fields, but must keep the first four.
+        if version.len() != 2
+            || version.eq_ignore_ascii_case("ff")
+            || (version == Self::VERSION && parts.next().is_some())
+            || trace_id.len() != 32
+            || span_id.len() != 16
+            || flags.len() != 2
+        {
+            return Err(());
+        }
+
+        u8::from_str_radix(version, 16).map_err(|_| ())?;
+        u8::from_str_radix(flags, 16).map_err(|_| ())?;
+
+        let trace_id = u128::from_str_radix(trace_id, 16).map_err(|_| ())?;
+        let span_id = u64::from_str_radix(span_id, 16).map_err(|_| ())?;
+
+        if trace_id == 0 || span_id == 0 {
+            return Err(());
+        }
+
+        Ok(Self { trace_id, span_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let raw = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let ctx = raw.parse::<SpanContext>().expect("is valid");
+
+        assert_eq!(ctx.trace_id, 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736);
+        assert_eq!(ctx.span_id, 0x00f0_67aa_0ba9_02b7);
+        assert_eq!(ctx.to_string(), raw);
+
+        let child = ctx.child();
+        assert_eq!(child.trace_id, ctx.trace_id);
+        assert_ne!(child.span_id, ctx.span_id);
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-xbf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(invalid.parse::<SpanContext>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,205 @@
+// Captures output that never goes through `tracing`: `println!`, C libraries
+// writing to fd 1 and 2 directly and so on. Each stream is redirected into a
+// pipe. A thread reads from it, writes everything back to the original fd
+// straight away and sends complete lines as records.
+//
+// The thread only ever writes to the original fds, never to the redirected
+// ones, so it can't capture itself. It is suppressed as well, in case
+// anything it calls logs. The writer's thread gets the original stderr too,
+// see `suppress::spawn`, otherwise everything it logs through the host's
+// formatter would come back as a record.
+//
+// Other threads can't be told apart once their output is in the pipe. What
+// the host's formatter prints for its own events is captured along with
+// everything else, so those events show up twice.
+//
+// Output that is still in the pipe when the process exits is lost, including
+// what would have gone to the terminal. Forwarding happens as soon as
+// anything is read to keep that window small.
+
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{Record, sink::EmitterSender, suppress};
+
+// Lines longer than this are split into multiple records.
+const MAX_LINE: usize = 64 * 1024;
+
+// Returns the original stderr. Nothing is sent while `disabled` is set, the
+// same as the layer, output still reaches the terminal.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn install(
+    tx: &EmitterSender<Record>,
+    disabled: &Arc<AtomicBool>,
+) -> io::Result<File> {
+    redirect(libc::STDOUT_FILENO, "stdout", tx.clone(), disabled.clone())?;
+    redirect(libc::STDERR_FILENO, "stderr", tx.clone(), disabled.clone())
+}
+
+// Returns the original `fd`, for writing around the redirect.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn redirect(
+    fd: RawFd,
+    source: &'static str,
+    tx: EmitterSender<Record>,
+    disabled: Arc<AtomicBool>,
+) -> io::Result<File> {
+    // SAFETY: `dup` only creates a new descriptor, `fd` is left alone.
+    let original = unsafe { libc::dup(fd) };
+    if original < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor was just created and nothing else owns it.
+    let original = File::from(unsafe { OwnedFd::from_raw_fd(original) });
+    let around = original.try_clone()?;
+
+    let (reader, writer) = io::pipe()?;
+
+    // The thread is started first, if redirecting fails it sees the pipe
+    // close and exits.
+    std::thread::Builder::new()
+        .name(format!("laminar-{source}"))
+        .spawn(move || {
+            let _suppress = suppress::enter();
+            forward(reader, original, source, &tx, &disabled);
+        })?;
+
+    // SAFETY: both descriptors are open, `fd` is closed and replaced by the
+    // write end of the pipe.
+    if unsafe { libc::dup2(writer.as_raw_fd(), fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(around)
+}
+
+fn forward(
+    mut reader: impl Read,
+    mut original: impl Write,
+    source: &'static str,
+    tx: &EmitterSender<Record>,
+    disabled: &AtomicBool,
+) {
+    let send = |line: &[u8]| {
+        if !disabled.load(Ordering::Relaxed) {
+            send(tx, source, line);
+        }
+    };
+
+    let mut buf = [0; 8192];
+    let mut line = Vec::new();
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+
+        // Whoever is watching the terminal comes first.
+        original.write_all(&buf[..n]).ok();
+
+        line.extend_from_slice(&buf[..n]);
+        while let Some(end) = line.iter().position(|b| *b == b'\n') {
+            let rest = line.split_off(end + 1);
+            send(&line);
+            line = rest;
+        }
+
+        if line.len() >= MAX_LINE {
+            send(&line);
+            line.clear();
+        }
+    }
+
+    if !line.is_empty() {
+        send(&line);
+    }
+}
+
+fn send(tx: &EmitterSender<Record>, source: &'static str, line: &[u8]) {
+    metrics::counter!("capture.line", "source" => source).increment(1);
+
+    let message = String::from_utf8_lossy(line);
+    tx.send(
+        Record::builder()
+            .source(source.to_string())
+            .message(message.trim_end_matches(['\n', '\r']).to_string())
+            .fields("{}")
+            .build(),
+    )
+    .ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::sink::emitter;
+
+    #[test]
+    fn test_redirect() -> io::Result<()> {
+        let (tx, mut rx) = emitter(10);
+
+        // Stands in for stdout, whatever is written to `fake` should show up
+        // on `terminal` and as records.
+        let (mut terminal, mut fake) = io::pipe()?;
+        redirect(
+            fake.as_raw_fd(),
+            "stdout",
+            tx,
+            Arc::new(AtomicBool::new(false)),
+        )?;
+
+        fake.write_all(b"hello\r\nwor")?;
+        fake.write_all(b"ld")?;
+        drop(fake);
+
+        let mut output = String::new();
+        terminal.read_to_string(&mut output)?;
+        assert_eq!(output, "hello\r\nworld");
+
+        let messages = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|record| {
+                assert_eq!(record.source.as_deref(), Some("stdout"));
+                Arc::unwrap_or_clone(record).message
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["hello", "world"]);
+
+        Ok(())
+    }
+
+    // Without a remote the output is passed through, nothing is sent.
+    #[test]
+    fn test_disabled() -> io::Result<()> {
+        let (tx, mut rx) = emitter(10);
+
+        let (mut terminal, mut fake) = io::pipe()?;
+        redirect(
+            fake.as_raw_fd(),
+            "stdout",
+            tx,
+            Arc::new(AtomicBool::new(true)),
+        )?;
+
+        fake.write_all(b"hello\n")?;
+        drop(fake);
+
+        let mut output = String::new();
+        terminal.read_to_string(&mut output)?;
+        assert_eq!(output, "hello\n");
+        assert!(rx.try_recv().is_err());
+
+        Ok(())
+    }
+}
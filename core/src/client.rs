@@ -0,0 +1,400 @@
+// Sends records without `tracing`, eg from a test harness, a build system or
+// `laminar tap`. The writer runs on its own thread, the same way as the one
+// started by `StreamLayerBuilder::spawn`.
+//
+// Dropping the client flushes anything that is still buffered, waiting a few
+// seconds at most.
+
+use std::{
+    fmt,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, ready},
+};
+
+use eyre::Result;
+use futures::{
+    StreamExt,
+    future::BoxFuture,
+    stream::{self, BoxStream},
+};
+use tokio::sync::watch;
+
+use crate::{
+    Config, ConnectionState, Record, SourceProcess, Writer, WriterGuard,
+    config::LayerConfig,
+    sink::{EmitterOpts, EmitterSender, Status, emitter},
+};
+
+#[derive(Debug, thiserror::Error)]
+#[error("writer has stopped")]
+pub struct ClosedError;
+
+pub struct Client {
+    tx: EmitterSender<Record>,
+    status: watch::Receiver<Status>,
+    // Records handed to the writer so far, `flush` waits for the driver to
+    // have processed this many.
+    sent: AtomicU64,
+    // In-progress `poll_flush`.
+    flushing: Option<BoxFuture<'static, ()>>,
+    // In-progress `poll_next`, see the `Stream` impl.
+    states: Option<BoxStream<'static, ConnectionState>>,
+    _guard: WriterGuard,
+}
+
+#[bon::bon]
+impl Client {
+    // Blocks until the writer's endpoint is bound, use `connect` from async
+    // code.
+    #[builder]
+    pub fn new(
+        // Defaults to the layer config from `Config::load`.
+        config: Option<LayerConfig>,
+        // The process the records came from, if any.
+        source: Option<SourceProcess>,
+        #[builder(default = EmitterOpts::default().buffer_size)]
+        buffer_size: usize,
+    ) -> Result<Self> {
+        let (writer, status_tx, client) =
+            Self::prepare(config, source, buffer_size)?;
+        let guard = writer.spawn_with(Some(status_tx))?;
+
+        Ok(Self {
+            _guard: guard,
+            ..client
+        })
+    }
+
+    // `new` without blocking the runtime, eg
+    // `Client::connect().config(config).build().await`.
+    #[builder(finish_fn = build)]
+    pub async fn connect(
+        config: Option<LayerConfig>,
+        source: Option<SourceProcess>,
+        #[builder(default = EmitterOpts::default().buffer_size)]
+        buffer_size: usize,
+    ) -> Result<Self> {
+        let (writer, status_tx, client) =
+            Self::prepare(config, source, buffer_size)?;
+        let guard = writer.spawn_async(Some(status_tx)).await?;
+
+        Ok(Self {
+            _guard: guard,
+            ..client
+        })
+    }
+}
+
+impl Client {
+    // Everything but the writer's guard, which is only there once it has
+    // been started.
+    fn prepare(
+        config: Option<LayerConfig>,
+        source: Option<SourceProcess>,
+        buffer_size: usize,
+    ) -> Result<(Writer, watch::Sender<Status>, Self)> {
+        let config = match config {
+            Some(cfg) => cfg,
+            None => Config::load()?.layer(),
+        };
+
+        let (tx, rx) = emitter(buffer_size);
+        let (status_tx, status) = watch::channel(Status::default());
+
+        let writer = Writer::builder()
+            .rx(rx)
+            .config(config)
+            .maybe_source(source)
+            .build();
+
+        Ok((
+            writer,
+            status_tx,
+            Self {
+                tx,
+                status,
+                sent: AtomicU64::new(0),
+                flushing: None,
+                states: None,
+                _guard: WriterGuard::default(),
+            },
+        ))
+    }
+
+    pub fn send(&self, record: Record) -> Result<(), ClosedError> {
+        self.tx.send(record).map_err(|_| ClosedError)?;
+        self.sent.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    // Returns how many records were sent, stopping at the first failure.
+    pub fn send_batch(
+        &self,
+        records: impl IntoIterator<Item = Record>,
+    ) -> Result<usize, ClosedError> {
+        records.into_iter().try_fold(0, |sent, record| {
+            self.send(record)?;
+            Ok(sent + 1)
+        })
+    }
+
+    #[must_use]
+    pub fn state(&self) -> ConnectionState {
+        // There's no driver at all when the remote isn't configured.
+        if self.tx.is_closed() {
+            return ConnectionState::Stopped;
+        }
+
+        self.status.borrow().state
+    }
+
+    // Waits for the first connection attempt to resolve, either `Connected`
+    // or `Stopped`. The future doesn't borrow the client.
+    pub fn connected(
+        &self,
+    ) -> impl Future<Output = ConnectionState> + Send + use<> {
+        let mut status = self.status.clone();
+
+        async move {
+            status
+                .wait_for(|status| status.state != ConnectionState::Connecting)
+                .await
+                .map_or(ConnectionState::Stopped, |status| status.state)
+        }
+    }
+
+    // Waits until everything sent so far has been written to the stream. This
+    // doesn't return while disconnected, use a timeout.
+    pub fn flush(&self) -> impl Future<Output = ()> + Send + use<> {
+        flush(self.status.clone(), self.sent.load(Ordering::Relaxed))
+    }
+}
+
+// Also used by the panic hook, see `panic_hook::flush`.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) async fn flush(mut status: watch::Receiver<Status>, target: u64) {
+    status
+        .wait_for(|status| {
+            status.processed >= target
+                || status.state == ConnectionState::Stopped
+        })
+        .await
+        .ok();
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("state", &self.state())
+            .field("sent", &self.sent)
+            .finish_non_exhaustive()
+    }
+}
+
+// Lets a stream of records be forwarded with `StreamExt::forward` or
+// `SinkExt::send_all`. Flushing has the same semantics as `Client::flush`.
+impl futures::Sink<Record> for Client {
+    type Error = ClosedError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(if self.tx.is_closed() {
+            Err(ClosedError)
+        } else {
+            Ok(())
+        })
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: Record,
+    ) -> Result<(), Self::Error> {
+        self.send(item)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        let this = &mut *self;
+        let target = this.sent.load(Ordering::Relaxed);
+        let status = this.status.clone();
+
+        ready!(
+            this.flushing
+                .get_or_insert_with(|| Box::pin(flush(status, target)))
+                .as_mut()
+                .poll(cx)
+        );
+        this.flushing = None;
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+// The other half of the adapter, yields the current connection state and then
+// every change, ending once the writer has stopped.
+impl futures::Stream for Client {
+    type Item = ConnectionState;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let stopped = this.tx.is_closed();
+        let status = this.status.clone();
+
+        this.states
+            .get_or_insert_with(|| states(status, stopped).boxed())
+            .poll_next_unpin(cx)
+    }
+}
+
+fn states(
+    status: watch::Receiver<Status>,
+    stopped: bool,
+) -> impl futures::Stream<Item = ConnectionState> {
+    let initial = (!stopped).then_some(status);
+
+    // Along with the state last yielded, `None` before the first one.
+    stream::unfold(
+        (initial, None),
+        |(status, last): (Option<watch::Receiver<_>>, _)| async move {
+            if last == Some(ConnectionState::Stopped) {
+                return None;
+            }
+
+            // No driver, or it's gone without saying so.
+            let Some(mut status) = status else {
+                return Some((
+                    ConnectionState::Stopped,
+                    (None, Some(ConnectionState::Stopped)),
+                ));
+            };
+
+            let state = status
+                .wait_for(|status| Some(status.state) != last)
+                .await
+                .map_or(ConnectionState::Stopped, |status| status.state);
+
+            Some((state, (Some(status), Some(state))))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt, stream};
+    use iroh::SecretKey;
+    use tokio::time;
+
+    use super::*;
+    use crate::config::KeySource;
+
+    fn record(message: &str) -> Record {
+        Record::builder()
+            .message(message.to_string())
+            .fields("{}")
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_disabled() -> Result<()> {
+        let client = Client::builder()
+            .config(LayerConfig::builder().key(KeySource::Ephemeral).build())
+            .build()?;
+
+        assert_eq!(client.state(), ConnectionState::Stopped);
+        assert_eq!(client.connected().await, ConnectionState::Stopped);
+        assert!(client.send(record("dropped")).is_err());
+
+        time::timeout(Duration::from_millis(100), client.flush()).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_states() -> Result<()> {
+        let disabled = Client::connect()
+            .config(LayerConfig::builder().key(KeySource::Ephemeral).build())
+            .build()
+            .await?;
+
+        assert_eq!(
+            disabled.collect::<Vec<_>>().await,
+            [ConnectionState::Stopped]
+        );
+
+        let keypair = SecretKey::from_bytes(&rand::random::<[u8; 32]>());
+        let mut client = Client::connect()
+            .config(
+                LayerConfig::builder()
+                    .key(KeySource::Ephemeral)
+                    .remote(keypair.public())
+                    .build(),
+            )
+            .build()
+            .await?;
+
+        assert_eq!(client.next().await, Some(ConnectionState::Connecting));
+
+        // Nothing changes until the remote answers, which it never does.
+        assert!(
+            time::timeout(Duration::from_millis(100), client.next())
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_buffered() -> Result<()> {
+        let keypair = SecretKey::from_bytes(&rand::random::<[u8; 32]>());
+        let mut client = Client::builder()
+            .config(
+                LayerConfig::builder()
+                    .key(KeySource::Ephemeral)
+                    .remote(keypair.public())
+                    .build(),
+            )
+            .build()?;
+
+        assert_eq!(client.state(), ConnectionState::Connecting);
+        assert_eq!(client.send_batch([record("a"), record("b")])?, 2);
+
+        client.feed(record("c")).await?;
+
+        // Nothing can be written until there's a connection, and `send_all`
+        // flushes once the stream is done.
+        assert!(
+            time::timeout(
+                Duration::from_millis(100),
+                client.send_all(&mut stream::iter([Ok(record("d"))])),
+            )
+            .await
+            .is_err()
+        );
+        assert!(
+            time::timeout(Duration::from_millis(100), client.flush())
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
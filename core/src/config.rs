@@ -1,6 +1,14 @@
 mod keys;
+mod network;
+mod peers;
 
-use eyre::Result;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use eyre::{Result, WrapErr, bail, eyre};
 use figment::{
     Figment, Profile, Provider,
     providers::{self, Format},
@@ -8,16 +16,95 @@ use figment::{
 };
 use iroh::PublicKey;
 use serde::{Deserialize, Serialize};
+use serde_with::{DurationSeconds, serde_as};
 
-pub use crate::config::keys::KeySource;
+pub use crate::config::{
+    keys::{KeyLock, KeySource},
+    network::{NetworkConfig, NetworkMode, Reachability},
+    peers::{Peer, Remote},
+};
+use crate::{
+    api::LabelSource, recorder::RecorderConfig, redact::RedactConfig,
+    sampling::SamplingConfig,
+};
 
+// Each of the flags is an independent opt-in.
+#[allow(clippy::struct_excessive_bools)]
+#[serde_as]
 #[derive(Debug, Deserialize, Serialize, Clone, bon::Builder)]
 pub struct LayerConfig {
-    // TODO: I think it is possible to have a publickey (from str) that causes
-    // an RemoteStateActorStoppedError. There needs to be a better validator
-    // here because the error is extremely weird when it gets to the driver.
-    pub remote: Option<PublicKey>,
+    // Checked by `Remote::check` before the writer dials it, a key can parse
+    // and still not be something iroh can connect to.
+    #[builder(into)]
+    pub remote: Option<Remote>,
     pub display_name: Option<String>,
+    // The writer's endpoint key, which is how the sink recognizes it across
+    // restarts. By default, there's a key file per process name in
+    // `~/.config/laminar/writers`.
+    #[serde(default)]
+    #[builder(default)]
+    pub key: KeySource,
+    // Sent along with the writer's identity, these take precedence over
+    // anything that was detected.
+    #[serde(default)]
+    #[builder(default)]
+    pub labels: BTreeMap<String, String>,
+    // Labels to fill in automatically. Nothing is detected by default.
+    #[serde(default)]
+    #[builder(default)]
+    pub detect_labels: Vec<LabelSource>,
+    // How often, in seconds, to send a resource sample (cpu, memory, ...) of
+    // the source process. Sampling is disabled when unset.
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    #[serde(default)]
+    pub resource_interval: Option<Duration>,
+    // Include the fields of every span an event is in, not just their names
+    // and ids.
+    #[serde(default)]
+    #[builder(default)]
+    pub span_fields: bool,
+    #[serde(default)]
+    #[builder(default)]
+    pub sampling: SamplingConfig,
+    // `EnvFilter` directives applied only to the stream layer, eg
+    // `info,my_app=debug`. This is independent of any other layer's filter.
+    // Spans with a `laminar.capture = true` field, and everything inside of
+    // them, bypass it.
+    #[builder(into)]
+    pub filter: Option<String>,
+    // Install a panic hook that sends the panic (with a backtrace) before
+    // handing off to the previous hook.
+    #[serde(default)]
+    #[builder(default)]
+    pub capture_panics: bool,
+    // Capture a backtrace for every error level event. This is expensive,
+    // the whole stack is resolved each time.
+    #[serde(default)]
+    #[builder(default)]
+    pub error_backtraces: bool,
+    // Redirect the process's stdout and stderr through the layer, each line
+    // is sent with `stdout` or `stderr` as its source. Output still reaches
+    // the terminal. Unix only.
+    //
+    // Events that another layer prints, eg `tracing_subscriber::fmt`, are
+    // sent twice: as the event and as the captured line.
+    #[serde(default)]
+    #[builder(default)]
+    pub capture_output: bool,
+    #[serde(default)]
+    #[builder(default)]
+    pub redact: RedactConfig,
+    #[serde(default)]
+    #[builder(default)]
+    pub recorder: RecorderConfig,
+    #[serde(default)]
+    #[builder(default)]
+    pub network: NetworkConfig,
+    // Apply changes to the config file while the writer is running, see
+    // `StreamLayerBuilder::reload`.
+    #[serde(default)]
+    #[builder(default)]
+    pub reload: bool,
 }
 
 impl Default for LayerConfig {
@@ -29,22 +116,48 @@ impl Default for LayerConfig {
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct ReaderConfig {
     pub key: KeySource,
+    #[serde(default)]
+    pub network: NetworkConfig,
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     layer: LayerConfig,
     reader: ReaderConfig,
+    // Names that can be used instead of a public key, eg for `remote`.
+    #[serde(default)]
+    peers: BTreeMap<String, Peer>,
+    // The file this was loaded from, whether it exists or not.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(skip)]
+    profile: Profile,
 }
 
-// TODO:
-// - Document how to set the sub-keys, eg FOO={key={path="asdf"}}
+// Nested keys can be set from the environment with `__` as the separator, eg
+// `LAMINAR_LAYER__FILTER=debug` or `LAMINAR_READER__KEY={path="asdf"}`.
+//
+// Profiles are tables under `profiles`. The selected profile
+// (`LAMINAR_PROFILE`) is merged over the rest of the file, and the environment
+// is merged over both:
+//
+// ```toml
+// [layer]
+// filter = "info"
+//
+// [profiles.work.layer]
+// remote = "ci-sink"
+// ```
 impl Config {
     const PATH_ENV: &'static str = "LAMINAR_CONFIG";
+    const PROFILE_ENV: &'static str = "LAMINAR_PROFILE";
     const PATH: &'static str = "~/.config/laminar/config.toml";
     const ENV_PREFIX: &'static str = "LAMINAR_";
+    // Everything else in the file is a mistake, see `check`.
+    const TABLES: &'static [&'static str] = &["layer", "reader", "peers"];
+    const PROFILES: &'static str = "profiles";
 
-    fn figment_with_path(path: Option<&str>) -> Figment {
+    pub(crate) fn resolve_path(path: Option<&str>) -> PathBuf {
         let path = path.map_or_else(
             || {
                 std::env::var(Self::PATH_ENV)
@@ -53,26 +166,184 @@ impl Config {
             str::to_string,
         );
 
+        PathBuf::from(shellexpand::tilde(&path).as_ref())
+    }
+
+    fn resolve_profile(profile: Option<&str>) -> Profile {
+        profile.map_or_else(
+            || Profile::from_env_or(Self::PROFILE_ENV, Profile::Default),
+            Profile::new,
+        )
+    }
+
+    fn figment_with_path(path: &Path, profile: Profile) -> Figment {
         Figment::from(Self::default())
-            .merge(providers::Toml::file(shellexpand::tilde(&path).as_ref()))
-            .merge(providers::Env::prefixed(Self::ENV_PREFIX))
+            .merge(providers::Toml::file(path))
+            .merge(Profiles(path.to_path_buf()))
+            .merge(
+                providers::Env::prefixed(Self::ENV_PREFIX)
+                    .ignore(&["config", "profile"])
+                    .split("__")
+                    .global(),
+            )
+            .select(profile)
+    }
+
+    // `None` falls back to `LAMINAR_CONFIG` and `LAMINAR_PROFILE`.
+    #[allow(clippy::result_large_err)]
+    pub fn load_with(
+        path: Option<&str>,
+        profile: Option<&str>,
+    ) -> Result<Self, figment::Error> {
+        let path = Self::resolve_path(path);
+        let profile = Self::resolve_profile(profile);
+
+        let mut config: Self =
+            Self::figment_with_path(&path, profile.clone()).extract()?;
+        config.path = Some(path);
+        config.profile = profile;
+
+        Ok(config)
     }
 
     #[allow(clippy::result_large_err)]
     pub fn load() -> Result<Self, figment::Error> {
-        Self::figment_with_path(None).extract()
+        Self::load_with(None, None)
     }
 
     #[allow(clippy::result_large_err)]
     pub fn load_from_path(
         path: impl AsRef<str>,
     ) -> Result<Self, figment::Error> {
-        Self::figment_with_path(Some(path.as_ref())).extract()
+        Self::load_with(Some(path.as_ref()), None)
+    }
+
+    #[must_use]
+    pub const fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    fn figment(&self) -> Figment {
+        Self::figment_with_path(&self.path(), self.profile.clone())
+    }
+
+    // Looks up where values came from. The file is read again, so hold on to
+    // this for more than one key.
+    #[must_use]
+    pub fn provenance(&self) -> Provenance {
+        Provenance {
+            figment: self.figment(),
+            profile: self.profile.clone(),
+        }
+    }
+
+    // Everything that would otherwise only fail once a writer or reader is
+    // started, or be silently ignored: unknown keys and profiles, remotes that
+    // can't be dialed and key files that can't be read.
+    pub fn check(&self) -> Result<()> {
+        let path = self.path();
+        let file = providers::Toml::file(&path)
+            .data()?
+            .remove(&Profile::Default)
+            .unwrap_or_default();
+
+        let mut profiles = Dict::new();
+        for (key, value) in file {
+            match value.into_dict() {
+                Some(dict) if key == Self::PROFILES => profiles = dict,
+                _ if Self::TABLES.contains(&key.as_str()) => {}
+                _ => bail!("unknown key `{key}` in {}", path.display()),
+            }
+        }
+
+        for (name, profile) in profiles {
+            let Some(profile) = profile.into_dict() else {
+                bail!("profile `{name}` isn't a table");
+            };
+
+            if let Some(key) = profile
+                .keys()
+                .find(|key| !Self::TABLES.contains(&key.as_str()))
+            {
+                bail!("unknown key `{key}` in profile `{name}`");
+            }
+        }
+
+        if self.profile != Profile::Default
+            && !self.figment().profiles().any(|p| *p == self.profile)
+        {
+            bail!("profile `{}` isn't in {}", self.profile, path.display());
+        }
+
+        for (name, peer) in &self.peers {
+            peer.check().wrap_err_with(|| format!("peer `{name}`"))?;
+        }
+
+        if let Some(remote) = &self.layer().remote {
+            remote.check().wrap_err("layer.remote")?;
+        }
+
+        // A missing key file isn't a problem, it is created on first use.
+        if let Some(file) = self.reader.key.reader_file() {
+            KeySource::read(&file).wrap_err("reader.key")?;
+        } else {
+            self.reader.key.load().wrap_err("reader.key")?;
+        }
+
+        Ok(())
+    }
+
+    // The file the config was loaded from, or would have been if it existed.
+    #[must_use]
+    pub fn path(&self) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| Self::resolve_path(None))
     }
 
+    // A `remote` set to a peer's name is replaced with its entry from the
+    // address book. Unknown names are left as-is, the writer reports them.
     #[must_use]
     pub fn layer(&self) -> LayerConfig {
-        self.layer.clone()
+        let mut layer = self.layer.clone();
+        if let Some(Remote::Name(name)) = &layer.remote
+            && let Ok(remote) = self.resolve(name)
+        {
+            layer.remote = Some(remote);
+        }
+
+        layer
+    }
+
+    #[must_use]
+    pub const fn peers(&self) -> &BTreeMap<String, Peer> {
+        &self.peers
+    }
+
+    // The name `key` has in the address book, if any.
+    #[must_use]
+    pub fn peer_name(&self, key: &PublicKey) -> Option<&str> {
+        self.peers
+            .iter()
+            .find(|(_, peer)| peer.key == *key)
+            .map(|(name, _)| name.as_str())
+    }
+
+    // Either the name of a peer or a public key.
+    pub fn resolve(&self, remote: &str) -> Result<Remote> {
+        if let Some(peer) = self.peers.get(remote) {
+            return Ok(Remote::Peer {
+                name: remote.to_string(),
+                peer: peer.clone(),
+            });
+        }
+
+        remote.parse::<PublicKey>().map(Remote::Key).map_err(|_| {
+            eyre!(
+                "unknown peer `{remote}`, add it with `laminar peers add` or \
+                 use a public key"
+            )
+        })
     }
 
     #[must_use]
@@ -82,13 +353,79 @@ impl Config {
 
     #[must_use]
     pub fn split(&self) -> (LayerConfig, ReaderConfig) {
-        (self.layer.clone(), self.reader.clone())
+        (self.layer(), self.reader.clone())
+    }
+}
+
+impl Config {
+    const METADATA_NAME: &'static str = "laminar defaults";
+}
+
+// The tables under `profiles` in the config file, each one is a profile.
+struct Profiles(PathBuf);
+
+impl Provider for Profiles {
+    fn metadata(&self) -> figment::Metadata {
+        figment::Metadata::from("TOML file", self.0.as_path())
+    }
+
+    fn data(
+        &self,
+    ) -> Result<figment::value::Map<Profile, Dict>, figment::Error> {
+        let profiles = providers::Toml::file(&self.0)
+            .data()?
+            .remove(&Profile::Default)
+            .and_then(|mut file| file.remove(Config::PROFILES))
+            .and_then(figment::value::Value::into_dict)
+            .unwrap_or_default();
+
+        // Anything that isn't a table is reported by `Config::check`.
+        Ok(profiles
+            .into_iter()
+            .filter_map(|(name, profile)| {
+                Some((Profile::new(&name), profile.into_dict()?))
+            })
+            .collect())
+    }
+}
+
+// See `Config::provenance`.
+#[derive(Debug)]
+pub struct Provenance {
+    figment: Figment,
+    profile: Profile,
+}
+
+impl Provenance {
+    // Where the value at `key` (eg `layer.filter`) came from, `None` for keys
+    // that aren't set anywhere.
+    #[must_use]
+    pub fn of(&self, key: &str) -> Option<String> {
+        let value = self.figment.find_value(key).ok()?;
+        let metadata = self.figment.get_metadata(value.tag())?;
+
+        // Tags only know that a value came from a custom profile, not which
+        // one, only the selected one is ever merged though.
+        let custom = value.tag().profile().is_none();
+
+        Some(match &metadata.source {
+            Some(source) if custom => format!("{source} [{}]", self.profile),
+            Some(source) => source.to_string(),
+            None if metadata.name == Config::METADATA_NAME => {
+                "default".to_string()
+            }
+            None => format!(
+                "{}{}",
+                Config::ENV_PREFIX,
+                key.to_ascii_uppercase().replace('.', "__")
+            ),
+        })
     }
 }
 
 impl Provider for Config {
     fn metadata(&self) -> figment::Metadata {
-        figment::Metadata::named("inspector/layer")
+        figment::Metadata::named(Self::METADATA_NAME)
     }
 
     fn data(
@@ -102,6 +439,116 @@ impl Provider for Config {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_peers() -> Result<()> {
+        let key =
+            iroh::SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public();
+        let cfg: Config = Figment::from(Config::default())
+            .merge(providers::Toml::string(&format!(
+                r#"
+            [layer]
+            remote = "alice"
+
+            [peers.alice]
+            key = "{key}"
+            addrs = ["127.0.0.1:4000"]
+            "#
+            )))
+            .extract()?;
+
+        let Some(Remote::Peer { name, peer }) = cfg.layer().remote else {
+            panic!("expected a peer: {:?}", cfg.layer().remote);
+        };
+        assert_eq!(name, "alice");
+        assert_eq!(peer.key, key);
+        assert_eq!(cfg.peer_name(&key), Some("alice"));
+
+        assert_eq!(cfg.resolve(&key.to_string())?, Remote::Key(key));
+        assert!(cfg.resolve("bob").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_profiles() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-config-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [layer]
+            filter = "info"
+            span_fields = true
+
+            [profiles.work.layer]
+            filter = "debug"
+            "#,
+        )?;
+        let path = path.to_string_lossy();
+
+        let cfg = Config::load_with(Some(&path), Some("default"))?;
+        assert_eq!(cfg.layer().filter.as_deref(), Some("info"));
+        let provenance = cfg.provenance();
+        assert_eq!(provenance.of("layer.filter").as_deref(), Some(&*path));
+        assert_eq!(
+            provenance.of("layer.capture_panics").as_deref(),
+            Some("default")
+        );
+        assert!(cfg.check().is_ok());
+
+        let work = Config::load_with(Some(&path), Some("work"))?;
+        assert_eq!(work.layer().filter.as_deref(), Some("debug"));
+        assert!(work.layer().span_fields);
+        assert_eq!(
+            work.provenance().of("layer.filter"),
+            Some(format!("{path} [work]"))
+        );
+
+        let home = Config::load_with(Some(&path), Some("home"))?;
+        assert_eq!(home.layer().filter.as_deref(), Some("info"));
+        assert!(home.check().is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_keys() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-config-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("config.toml");
+        let check = |contents: &str, profile: &str| -> Result<()> {
+            std::fs::write(&path, contents)?;
+            Config::load_with(Some(&path.to_string_lossy()), Some(profile))?
+                .check()
+        };
+
+        let valid = r#"
+            [layer]
+            filter = "info"
+
+            [profiles.work.layer]
+            filter = "debug"
+            "#;
+        check(valid, "work")?;
+        // Only tables under `profiles` are profiles.
+        assert!(check(valid, "layer").is_err());
+
+        // Misspelled tables aren't taken for profiles.
+        assert!(check("[layr]\nfilter = \"info\"", "default").is_err());
+        assert!(
+            check("[profiles.work.layr]\nfilter = \"info\"", "work").is_err()
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
     #[test]
     fn test_config() -> Result<()> {
         // use laminar_testing as _;
@@ -1,4 +1,10 @@
-use std::{io::ErrorKind, path::PathBuf, str::FromStr};
+use std::{
+    fs::{File, TryLockError},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use data_encoding::BASE32_NOPAD;
 use eyre::{Result, WrapErr, eyre};
@@ -6,8 +12,15 @@ use iroh::SecretKey;
 use serde::{Deserialize, Serialize};
 use shellexpand::tilde;
 
-const DEFAULT_KEY_PATH: &str = "~/.config/laminar/reader.key";
+const WRITER_KEY_DIR: &str = "~/.config/laminar/writers";
 
+// How many writers with the same name get a key that persists across
+// restarts, see `KeySource::load_writer`.
+const WRITER_SLOTS: usize = 8;
+
+// `None` uses a key file in a default location, created on first use. That's
+// `reader.key` for the reader, and one file per running writer instance (see
+// `load_writer`).
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum KeySource {
@@ -19,19 +32,66 @@ pub enum KeySource {
     Env {
         var: String,
     },
+    // A new key on every start, `key = "ephemeral"`.
+    #[serde(with = "ephemeral")]
+    Ephemeral,
 }
 
 impl KeySource {
-    fn path(&self) -> Option<&str> {
+    pub const READER_PATH: &'static str = "~/.config/laminar/reader.key";
+
+    // How long the key replaced by `rotate` is still accepted by the reader,
+    // giving writers a chance to pick up the new address.
+    pub const ROTATION_GRACE: Duration = Duration::from_hours(7 * 24);
+
+    // The default key file for the first writer sending records from `name`.
+    // Anything that doesn't belong in a file name is replaced.
+    #[must_use]
+    pub fn writer_path(name: &str) -> String {
+        Self::writer_slot_path(WRITER_KEY_DIR, name, 0)
+    }
+
+    // Every other running writer with the same name gets `name.<slot>.key`.
+    fn writer_slot_path(dir: &str, name: &str, slot: usize) -> String {
+        let name = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+
+        let name = name.trim_start_matches('.');
+        if slot == 0 {
+            format!("{dir}/{name}.key")
+        } else {
+            format!("{dir}/{name}.{slot}.key")
+        }
+    }
+
+    fn path<'a>(&'a self, default: &'a str) -> Option<&'a str> {
         match self {
-            Self::None => Some(DEFAULT_KEY_PATH),
+            Self::None => Some(default),
             Self::File { path } => Some(path),
-            Self::Env { .. } => None,
+            Self::Env { .. } | Self::Ephemeral => None,
         }
     }
 
-    fn key_path(&self) -> Option<PathBuf> {
-        self.path().map(|path| PathBuf::from(tilde(path).as_ref()))
+    // The file the key is stored in, `None` for keys that don't live in a
+    // file. `default` is used for `None`.
+    #[must_use]
+    pub fn file(&self, default: &str) -> Option<PathBuf> {
+        self.path(default)
+            .map(|path| PathBuf::from(tilde(path).as_ref()))
+    }
+
+    // The reader's key file, see `file`.
+    #[must_use]
+    pub fn reader_file(&self) -> Option<PathBuf> {
+        self.file(Self::READER_PATH)
     }
 
     fn parse_secret_key(value: &str, source: &str) -> Result<SecretKey> {
@@ -39,88 +99,486 @@ impl KeySource {
             .map_err(|e| eyre!("invalid secret key in {source}: {e}"))
     }
 
-    fn encode_secret_key(secret_key: &SecretKey) -> String {
+    // The format keys are stored in, which is also accepted by `decode`.
+    #[must_use]
+    pub fn encode(secret_key: &SecretKey) -> String {
         BASE32_NOPAD.encode(&secret_key.to_bytes())
     }
 
+    pub fn decode(value: &str) -> Result<SecretKey> {
+        Self::parse_secret_key(value, "input")
+    }
+
+    #[must_use]
+    pub fn generate() -> SecretKey {
+        SecretKey::from_bytes(&rand::random::<[u8; 32]>())
+    }
+
     pub fn load(&self) -> Result<SecretKey> {
-        if let Self::Env { var } = self {
-            let value = std::env::var(var)
-                .map_err(|_| eyre!("missing env var for reader key: {var}"))?;
-            return Self::parse_secret_key(&value, &format!("env var {var}"));
+        self.load_or(Self::READER_PATH)
+    }
+
+    // Same as `load`, with `default_path` used for `None`.
+    pub fn load_or(&self, default_path: &str) -> Result<SecretKey> {
+        match self {
+            Self::Env { var } => {
+                let value = std::env::var(var)
+                    .map_err(|_| eyre!("missing env var for key: {var}"))?;
+                return Self::parse_secret_key(
+                    &value,
+                    &format!("env var {var}"),
+                );
+            }
+            Self::Ephemeral => return Ok(Self::generate()),
+            Self::None | Self::File { .. } => {}
         }
 
         let key_path = self
-            .key_path()
+            .file(default_path)
             .ok_or_else(|| eyre!("missing path for file key source"))?;
 
-        let secret_key = match std::fs::read_to_string(&key_path) {
-            Ok(content) => {
-                Self::parse_secret_key(&content, &key_path.to_string_lossy())?
-            }
-            Err(err) if err.kind() == ErrorKind::NotFound => {
-                if let Some(parent) = key_path.parent() {
-                    std::fs::create_dir_all(parent)?;
-                }
+        if let Some(secret_key) = Self::read(&key_path)? {
+            return Ok(secret_key);
+        }
 
-                let secret_key =
-                    SecretKey::from_bytes(&rand::random::<[u8; 32]>());
-                std::fs::write(
-                    &key_path,
-                    Self::encode_secret_key(&secret_key),
-                )?;
-                secret_key
-            }
-            Err(err) => {
-                return Err(eyre!(
-                    "problem encountered loading key file {}:\n{err}",
-                    key_path.display()
-                ));
-            }
-        };
+        Self::create(&key_path)
+    }
 
-        Ok(secret_key)
+    // Same as `load_or`, without creating a key file that doesn't exist yet.
+    pub fn load_existing(&self, default_path: &str) -> Result<SecretKey> {
+        match self.file(default_path) {
+            Some(path) => Self::read(&path)?
+                .ok_or_else(|| eyre!("no key, run `laminar key generate`")),
+            None => self.load_or(default_path),
+        }
     }
 
-    pub async fn load_async(&self) -> Result<SecretKey> {
-        if let Self::Env { var } = self {
-            let value = std::env::var(var)
-                .map_err(|_| eyre!("missing env var for reader key: {var}"))?;
-            return Self::parse_secret_key(&value, &format!("env var {var}"));
+    // The key for a writer sending records from `name`. Writers on the same
+    // host sharing a key would replace each other's connection to the sink,
+    // so the default key file is locked for as long as the writer runs and
+    // the next writer with the same name moves on to the next one. Once all
+    // of them are taken, the writer gets a new key on every start.
+    //
+    // Explicitly configured keys are used as-is, sharing those is up to the
+    // config.
+    pub fn load_writer(
+        &self,
+        name: &str,
+    ) -> Result<(SecretKey, Option<KeyLock>)> {
+        self.load_writer_in(WRITER_KEY_DIR, name)
+    }
+
+    fn load_writer_in(
+        &self,
+        dir: &str,
+        name: &str,
+    ) -> Result<(SecretKey, Option<KeyLock>)> {
+        if !matches!(self, Self::None) {
+            return Ok((self.load_or(&Self::writer_path(name))?, None));
         }
 
-        let key_path = self
-            .key_path()
-            .ok_or_else(|| eyre!("missing path for file key source"))?;
+        for slot in 0..WRITER_SLOTS {
+            let path = PathBuf::from(
+                tilde(&Self::writer_slot_path(dir, name, slot)).as_ref(),
+            );
 
-        let secret_key = match tokio::fs::read_to_string(&key_path).await {
-            Ok(content) => {
-                Self::parse_secret_key(&content, &key_path.to_string_lossy())?
-            }
-            Err(err) if err.kind() == ErrorKind::NotFound => {
-                if let Some(parent) = key_path.parent() {
-                    tokio::fs::create_dir_all(parent).await?;
-                }
+            let Some(lock) = KeyLock::try_new(&path)? else {
+                continue;
+            };
 
-                let secret_key =
-                    SecretKey::from_bytes(&rand::random::<[u8; 32]>());
-                tokio::fs::write(
-                    &key_path,
-                    Self::encode_secret_key(&secret_key),
-                )
-                .await?;
-                secret_key
-            }
+            let secret_key = match Self::read(&path)? {
+                Some(secret_key) => secret_key,
+                None => Self::create(&path)?,
+            };
+
+            return Ok((secret_key, Some(lock)));
+        }
+
+        tracing::warn!(
+            name,
+            "all writer key files are in use, using a new key for this run",
+        );
+
+        Ok((Self::generate(), None))
+    }
+
+    pub async fn load_async(&self) -> Result<SecretKey> {
+        let source = self.clone();
+
+        tokio::task::spawn_blocking(move || source.load()).await?
+    }
+
+    // The key replaced by the last `rotate`, while it is still within
+    // `ROTATION_GRACE`.
+    pub fn load_previous(&self) -> Result<Option<SecretKey>> {
+        let Some(path) = self.reader_file().map(|path| previous_path(&path))
+        else {
+            return Ok(None);
+        };
+
+        let rotated = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        if rotated.elapsed().unwrap_or_default() > Self::ROTATION_GRACE {
+            tracing::debug!(path = %path.display(), "previous key expired");
+            return Ok(None);
+        }
+
+        Self::read(&path)
+    }
+
+    // `None` when the file doesn't exist.
+    pub fn read(path: &Path) -> Result<Option<SecretKey>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
             Err(err) => {
                 return Err(err).wrap_err_with(|| {
                     format!(
                         "problem encountered loading key file {}",
-                        key_path.display()
+                        path.display()
                     )
                 });
             }
         };
 
+        warn_permissions(path);
+
+        Self::parse_secret_key(&content, &path.to_string_lossy()).map(Some)
+    }
+
+    // Replaces the file, which is only readable by the current user. The key
+    // is written next to it first, a crash part way through leaves the old
+    // key in place instead of a truncated one.
+    pub fn write(path: &Path, secret_key: &SecretKey) -> Result<()> {
+        let temp = Self::write_temp(path, secret_key)?;
+
+        std::fs::rename(&temp, path)
+            .inspect_err(|_| {
+                std::fs::remove_file(&temp).ok();
+            })
+            .wrap_err_with(|| {
+                format!("unable to write key file {}", path.display())
+            })
+    }
+
+    // A new key in `path`, unless another process got there first. Then it's
+    // theirs that is used.
+    fn create(path: &Path) -> Result<SecretKey> {
+        let secret_key = Self::generate();
+        let temp = Self::write_temp(path, &secret_key)?;
+
+        let linked = std::fs::hard_link(&temp, path);
+        std::fs::remove_file(&temp).ok();
+
+        match linked {
+            Ok(()) => Ok(secret_key),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                Self::read(path)?.ok_or_else(|| {
+                    eyre!("key file {} disappeared", path.display())
+                })
+            }
+            Err(err) => Err(err).wrap_err_with(|| {
+                format!("unable to write key file {}", path.display())
+            }),
+        }
+    }
+
+    // A file in the same directory as `path`, so that it can be renamed over
+    // it.
+    fn write_temp(path: &Path, secret_key: &SecretKey) -> Result<PathBuf> {
+        let parent = path.parent().unwrap_or_else(|| Path::new("."));
+        std::fs::create_dir_all(parent)?;
+
+        let mut name = path.file_name().unwrap_or_default().to_owned();
+        name.push(format!(".{:016x}.tmp", rand::random::<u64>()));
+        let temp = parent.join(name);
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let written = options
+            .open(&temp)
+            .and_then(|mut file| {
+                file.write_all(Self::encode(secret_key).as_bytes())?;
+                file.sync_all()
+            })
+            .wrap_err_with(|| {
+                format!("unable to write key file {}", temp.display())
+            });
+
+        if written.is_err() {
+            std::fs::remove_file(&temp).ok();
+        }
+
+        written.map(|()| temp)
+    }
+
+    // Replaces the key in `path` with a new one. The old key is kept next to
+    // it, see `load_previous`.
+    pub fn rotate(path: &Path) -> Result<SecretKey> {
+        let previous = Self::read(path)?
+            .ok_or_else(|| eyre!("no key to rotate in {}", path.display()))?;
+        Self::write(&previous_path(path), &previous)?;
+
+        let secret_key = Self::generate();
+        Self::write(path, &secret_key)?;
+
         Ok(secret_key)
     }
 }
+
+// An exclusive lock on a writer's key file, held for as long as the writer
+// runs. The lock is on `<key>.lock` instead of the key itself, `write`
+// replaces the key file.
+#[derive(Debug)]
+pub struct KeyLock {
+    _file: File,
+}
+
+impl KeyLock {
+    // `None` when another writer holds it.
+    fn try_new(key: &Path) -> Result<Option<Self>> {
+        let mut path = key.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .wrap_err_with(|| format!("unable to open {}", path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(err)) => Err(err)
+                .wrap_err_with(|| format!("unable to lock {}", path.display())),
+        }
+    }
+}
+
+fn previous_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".previous");
+    PathBuf::from(name)
+}
+
+// Anyone that can read the key can impersonate the reader or writer.
+fn warn_permissions(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return;
+        };
+
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            tracing::warn!(
+                path = %path.display(),
+                mode = format!("{mode:o}"),
+                "key file is readable by other users, it should be 0600",
+            );
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+// `Ephemeral` is the string `"ephemeral"`, a unit variant would only match an
+// empty value in an untagged enum.
+mod ephemeral {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    const NAME: &str = "ephemeral";
+
+    pub(super) fn serialize<S: Serializer>(
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(NAME)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let value = String::deserialize(deserializer)?;
+        if value == NAME {
+            Ok(())
+        } else {
+            Err(D::Error::custom(format!("expected \"{NAME}\"")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        Figment,
+        providers::{Format, Toml},
+    };
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Wrapper {
+        key: KeySource,
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn parse(raw: &str) -> Result<KeySource, figment::Error> {
+        Figment::from(Toml::string(raw))
+            .extract::<Wrapper>()
+            .map(|wrapper| wrapper.key)
+    }
+
+    #[test]
+    fn test_deserialize() -> Result<()> {
+        assert!(matches!(
+            parse(r#"key = "ephemeral""#)?,
+            KeySource::Ephemeral
+        ));
+        assert!(matches!(
+            parse(r#"key = { path = "a.key" }"#)?,
+            KeySource::File { .. }
+        ));
+        assert!(parse(r#"key = "other""#).is_err());
+
+        assert_eq!(
+            serde_json::to_value(KeySource::Ephemeral)?,
+            serde_json::json!("ephemeral")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-keys-{}", rand::random::<u64>()));
+        let path = dir.join("reader.key");
+        let source = KeySource::File {
+            path: path.to_string_lossy().into_owned(),
+        };
+
+        let first = source.load()?;
+        assert_eq!(source.load()?.public(), first.public());
+        assert!(source.load_previous()?.is_none());
+
+        let second = KeySource::rotate(&path)?;
+        assert_ne!(second.public(), first.public());
+        assert_eq!(source.load()?.public(), second.public());
+        assert_eq!(
+            source.load_previous()?.map(|key| key.public()),
+            Some(first.public())
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    // Writers with the same name don't share a key while they're running, and
+    // get the same one back after a restart.
+    #[test]
+    fn test_load_writer() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-keys-{}", rand::random::<u64>()));
+        let root = dir.to_string_lossy();
+
+        let (first, first_lock) =
+            KeySource::None.load_writer_in(&root, "app")?;
+        let (second, second_lock) =
+            KeySource::None.load_writer_in(&root, "app")?;
+        assert_ne!(first.public(), second.public());
+        assert!(dir.join("app.key").exists());
+        assert!(dir.join("app.1.key").exists());
+
+        drop(second_lock);
+        let (again, _lock) = KeySource::None.load_writer_in(&root, "app")?;
+        assert_eq!(again.public(), second.public());
+
+        // Once every slot is taken, keys stop persisting.
+        let mut locks = vec![first_lock];
+        for _ in 2..WRITER_SLOTS {
+            locks.push(KeySource::None.load_writer_in(&root, "app")?.1);
+        }
+        let (_, lock) = KeySource::None.load_writer_in(&root, "app")?;
+        assert!(lock.is_none());
+
+        drop(locks);
+        std::fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    // Only the first key written wins, and nothing is left behind.
+    #[test]
+    fn test_create() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-keys-{}", rand::random::<u64>()));
+        let path = dir.join("writer.key");
+
+        let first = KeySource::create(&path)?;
+        let second = KeySource::create(&path)?;
+        assert_eq!(first.public(), second.public());
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+
+        std::fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    // Read-only commands don't leave a key file behind.
+    #[test]
+    fn test_load_existing() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-keys-{}", rand::random::<u64>()));
+        let path = dir.join("reader.key");
+        let source = KeySource::File {
+            path: path.to_string_lossy().into_owned(),
+        };
+
+        let err = source.load_existing("unused").expect_err("no key");
+        assert!(err.to_string().contains("laminar key generate"), "{err}");
+        assert!(!path.exists());
+
+        let key = source.load()?;
+        assert_eq!(source.load_existing("unused")?.public(), key.public());
+
+        std::fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_writer_path() {
+        assert_eq!(
+            KeySource::writer_path("my app"),
+            "~/.config/laminar/writers/my_app.key"
+        );
+        assert_eq!(
+            KeySource::writer_path("../etc/passwd"),
+            "~/.config/laminar/writers/_etc_passwd.key"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use iroh::{
+    Endpoint, RelayMode, address_lookup::MdnsAddressLookup, endpoint::Builder,
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{DurationSeconds, serde_as};
+use tokio::time;
+
+const ONLINE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkMode {
+    // iroh's relays and DNS based address lookup, along with mDNS.
+    #[default]
+    Global,
+    // Nothing that needs the internet. Peers are found with mDNS or dialed at
+    // the addresses in the address book, eg on an air-gapped network.
+    Local,
+}
+
+// ```toml
+// [layer.network]
+// mode = "local"
+// ```
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize, bon::Builder)]
+pub struct NetworkConfig {
+    #[serde(default)]
+    #[builder(default)]
+    pub mode: NetworkMode,
+    // How long, in seconds, to wait for a relay at startup. Without one, only
+    // peers that can be reached directly can connect until it shows up.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "online_timeout")]
+    #[builder(default = ONLINE_TIMEOUT)]
+    pub online_timeout: Duration,
+}
+
+const fn online_timeout() -> Duration {
+    ONLINE_TIMEOUT
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl NetworkConfig {
+    pub(crate) fn endpoint(&self) -> Builder {
+        match self.mode {
+            NetworkMode::Global => Endpoint::builder(),
+            NetworkMode::Local => Endpoint::empty_builder(RelayMode::Disabled),
+        }
+        .address_lookup(MdnsAddressLookup::builder())
+    }
+
+    // Doesn't block on `Endpoint::online`, the relay can still show up after
+    // this returns.
+    pub(crate) async fn online(&self, endpoint: &Endpoint) -> Reachability {
+        if self.mode == NetworkMode::Global
+            && time::timeout(self.online_timeout, endpoint.online())
+                .await
+                .is_err()
+        {
+            tracing::warn!(
+                timeout = ?self.online_timeout,
+                "no relay reachable, continuing with direct addresses",
+            );
+        }
+
+        Reachability::of(self.mode, endpoint)
+    }
+}
+
+// How an endpoint can be reached right now.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, strum::Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Reachability {
+    // `NetworkMode::Local`, only from the same network.
+    Local,
+    // No relay (yet), only peers that can dial one of the endpoint's
+    // addresses.
+    Direct,
+    // From anywhere, through the home relay.
+    Relay,
+}
+
+impl Reachability {
+    pub(crate) fn of(mode: NetworkMode, endpoint: &Endpoint) -> Self {
+        if mode == NetworkMode::Local {
+            return Self::Local;
+        }
+
+        if endpoint.addr().relay_urls().next().is_some() {
+            Self::Relay
+        } else {
+            Self::Direct
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    // Binds and reports back without a relay, none is reachable from here
+    // anyway.
+    #[tokio::test]
+    async fn test_local() -> eyre::Result<()> {
+        let network = NetworkConfig::builder()
+            .mode(NetworkMode::Local)
+            .online_timeout(Duration::from_mins(10))
+            .build();
+
+        let endpoint = network
+            .endpoint()
+            .secret_key(SecretKey::from_bytes(&rand::random::<[u8; 32]>()))
+            .bind()
+            .await?;
+
+        let reachability =
+            time::timeout(Duration::from_secs(5), network.online(&endpoint))
+                .await?;
+        assert_eq!(reachability, Reachability::Local);
+        assert_eq!(endpoint.addr().relay_urls().count(), 0);
+
+        endpoint.close().await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config() -> eyre::Result<()> {
+        let network: NetworkConfig =
+            serde_json::from_str(r#"{"mode": "local"}"#)?;
+        assert_eq!(network.mode, NetworkMode::Local);
+        assert_eq!(network.online_timeout, ONLINE_TIMEOUT);
+
+        Ok(())
+    }
+}
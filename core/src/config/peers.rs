@@ -0,0 +1,189 @@
+use std::{fmt, net::SocketAddr};
+
+use eyre::{Result, bail, eyre};
+use iroh::{EndpointAddr, PublicKey, RelayUrl};
+use serde::{Deserialize, Serialize, Serializer};
+
+// An entry in the `[peers]` address book, eg
+//
+// ```toml
+// [peers.alice]
+// key = "4b5d643d..."
+// addrs = ["192.168.1.20:41234"]
+// relays = ["https://relay.example.com"]
+// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, bon::Builder)]
+pub struct Peer {
+    pub key: PublicKey,
+    // Addresses the peer can be reached at directly, tried alongside
+    // discovery.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default)]
+    pub addrs: Vec<SocketAddr>,
+    // Relays the peer is known to be using.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default)]
+    pub relays: Vec<RelayUrl>,
+}
+
+impl Peer {
+    #[must_use]
+    pub fn addr(&self) -> EndpointAddr {
+        let addr = self
+            .addrs
+            .iter()
+            .fold(EndpointAddr::new(self.key), |addr, ip| {
+                addr.with_ip_addr(*ip)
+            });
+
+        self.relays
+            .iter()
+            .cloned()
+            .fold(addr, EndpointAddr::with_relay_url)
+    }
+
+    // Catches what parses but can't be dialed. Those otherwise only show up
+    // as an obscure error from iroh once the writer's driver is running.
+    pub fn check(&self) -> Result<()> {
+        check_key(&self.key)?;
+
+        for addr in &self.addrs {
+            if addr.ip().is_unspecified() || addr.port() == 0 {
+                bail!("{addr} isn't an address that can be dialed");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_key(key: &PublicKey) -> Result<()> {
+    // Small order points decode fine, nothing can be signed for them though.
+    if key.as_verifying_key().is_weak() {
+        bail!("{key} isn't a usable public key");
+    }
+
+    Ok(())
+}
+
+// Where a writer sends its records, `remote = "<public key>"` or the name of
+// a peer, `remote = "alice"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Remote {
+    Key(PublicKey),
+    // Looked up in the address book by `Config::layer`.
+    Name(String),
+    // A name that was found, it is written back out as just the name.
+    #[serde(skip_deserializing, serialize_with = "serialize_name")]
+    Peer {
+        name: String,
+        peer: Peer,
+    },
+}
+
+fn serialize_name<S: Serializer>(
+    name: &str,
+    _: &Peer,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(name)
+}
+
+impl Remote {
+    // `None` for names that weren't in the address book.
+    #[must_use]
+    pub const fn key(&self) -> Option<PublicKey> {
+        match self {
+            Self::Key(key) => Some(*key),
+            Self::Peer { peer, .. } => Some(peer.key),
+            Self::Name(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub fn addr(&self) -> Option<EndpointAddr> {
+        match self {
+            Self::Key(key) => Some((*key).into()),
+            Self::Peer { peer, .. } => Some(peer.addr()),
+            Self::Name(_) => None,
+        }
+    }
+
+    // The address to dial, see `Peer::check`.
+    pub fn check(&self) -> Result<EndpointAddr> {
+        match self {
+            Self::Key(key) => check_key(key)?,
+            Self::Peer { peer, .. } => peer.check()?,
+            Self::Name(name) => {
+                bail!("unknown peer `{name}`, add it with `laminar peers add`")
+            }
+        }
+
+        self.addr().ok_or_else(|| eyre!("{self} has no address"))
+    }
+}
+
+impl From<PublicKey> for Remote {
+    fn from(key: PublicKey) -> Self {
+        Self::Key(key)
+    }
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "{key}"),
+            Self::Name(name) | Self::Peer { name, .. } => f.write_str(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    #[test]
+    fn test_remote() -> Result<(), serde_json::Error> {
+        let key = SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public();
+
+        let parsed: Remote =
+            serde_json::from_value(serde_json::json!(key.to_string()))?;
+        assert_eq!(parsed, Remote::Key(key));
+
+        let parsed: Remote =
+            serde_json::from_value(serde_json::json!("alice"))?;
+        assert_eq!(parsed, Remote::Name("alice".into()));
+
+        let peer = Peer::builder()
+            .key(key)
+            .addrs(vec!["127.0.0.1:4000".parse().expect("valid")])
+            .build();
+        let remote = Remote::Peer {
+            name: "alice".into(),
+            peer,
+        };
+        assert_eq!(serde_json::to_value(&remote)?, "alice");
+        assert_eq!(remote.key(), Some(key));
+        assert_eq!(remote.addr().map(|addr| addr.ip_addrs().count()), Some(1));
+        assert!(remote.check().is_ok());
+
+        assert!(Remote::Name("bob".into()).check().is_err());
+
+        let unspecified = Peer::builder()
+            .key(key)
+            .addrs(vec!["0.0.0.0:4000".parse().expect("valid")])
+            .build();
+        assert!(unspecified.check().is_err());
+
+        // The identity point, it decodes but is small order.
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        let weak = PublicKey::from_bytes(&identity).expect("decodes");
+        assert!(Remote::Key(weak).check().is_err());
+
+        Ok(())
+    }
+}
@@ -1,96 +1,534 @@
 mod api;
+#[cfg(unix)]
+mod capture;
+mod client;
 pub mod config;
+#[cfg(feature = "log")]
+pub mod logger;
+mod panic_hook;
+pub mod propagation;
 mod reader;
+pub mod recorder;
+pub mod redact;
+mod reload;
+pub mod sampling;
 pub mod sink;
+mod suppress;
 
-use std::sync::Arc;
+use std::{
+    future,
+    sync::{Arc, mpsc::RecvTimeoutError},
+    time::{Duration, Instant},
+};
 
+pub use client::{Client, ClosedError};
 use eyre::Result;
-use iroh::{Endpoint, EndpointAddr};
+use iroh::EndpointAddr;
 pub use reader::Reader;
-use tokio::{sync::broadcast, task::JoinHandle};
-use tracing::{Instrument, Subscriber};
-use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+use tokio::{
+    sync::{broadcast, oneshot, watch},
+    task::JoinHandle,
+};
+use tracing::{
+    Instrument, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{
+    Layer,
+    layer::{Context, Filter},
+    registry::{LookupSpan, SpanRef},
+};
 
-pub use crate::{api::*, config::Config};
+pub use crate::{api::*, config::Config, sink::ConnectionState};
 use crate::{
+    api::{JsonFields, visit_fields},
     config::LayerConfig,
-    sink::{EmitterOpts, EmitterSender, SinkDriver, emitter},
+    recorder::Recorder,
+    redact::Redactor,
+    reload::LayerState,
+    sampling::Sampler,
+    sink::{
+        EmitterOpts, EmitterSender, SinkDriver, Status, driver::Target, emitter,
+    },
 };
 
 const DROP_TARGET: &str = "laminar_stream::drop";
+const CAPTURE_FIELD: &str = "laminar.capture";
 
 #[derive(Debug, bon::Builder)]
 pub struct Writer {
     rx: broadcast::Receiver<Arc<Record>>,
     config: LayerConfig,
     source: Option<SourceProcess>,
+    // Follow changes to the config file, see `reload`. Without a remote, the
+    // writer waits for one to be configured instead of stopping.
+    #[builder(default)]
+    reload: bool,
+    // Kept in sync with the config when reloading.
+    #[builder(skip)]
+    layer: Option<Arc<LayerState>>,
+    // The original stderr while output is captured, see `suppress::spawn`.
+    #[builder(skip)]
+    output: Option<Arc<std::fs::File>>,
+    // Reports back to the panic hook, see `panic_hook::flush`.
+    #[builder(skip)]
+    status: Option<watch::Sender<Status>>,
 }
 
+type Ready = oneshot::Receiver<Result<(), iroh::endpoint::BindError>>;
+
 // TODO:
-// - Is there some way to defer close until the buffer has been flushed?
 // - Maybe this can be a raw function instead of a struct? Definitely not doing
 //   much.
 impl Writer {
+    const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
     pub async fn run(self) -> Result<JoinHandle<()>> {
-        // The `laminar_stream::drop` target is important. That's used for
-        // filtering so that event/span recursion doesn't happen.
-        // Anything child that has a parent including that target will
-        // end up being dropped by the layer automatically.
-        let span = tracing::error_span!(
-            target: DROP_TARGET,
-            parent: tracing::Span::current(),
-            "Writer::run"
-        );
+        let Some((handle, ready)) = self.start(None, None)? else {
+            return Ok(tokio::spawn(async {}));
+        };
 
-        let _drop = span.enter();
+        ready.await??;
+
+        Ok(handle)
+    }
 
-        tracing::info!(config = ?self.config, "starting writer");
+    // Blocking version of `run` that doesn't need a runtime, or even async
+    // code. Dropping the guard flushes anything that is still buffered.
+    pub fn spawn(self) -> Result<WriterGuard> {
+        self.spawn_with(None)
+    }
+
+    fn spawn_with(
+        self,
+        status: Option<watch::Sender<Status>>,
+    ) -> Result<WriterGuard> {
+        let (guard, ready) = self.spawn_guarded(status)?;
+        if let Some(ready) = ready {
+            futures::executor::block_on(ready)??;
+        }
 
-        let Some(addr): Option<EndpointAddr> =
-            self.config.remote.map(Into::into)
+        Ok(guard)
+    }
+
+    // `spawn_with` for async callers, waiting for the endpoint to bind doesn't
+    // block the runtime.
+    async fn spawn_async(
+        self,
+        status: Option<watch::Sender<Status>>,
+    ) -> Result<WriterGuard> {
+        let (guard, ready) = self.spawn_guarded(status)?;
+        if let Some(ready) = ready {
+            ready.await??;
+        }
+
+        Ok(guard)
+    }
+
+    // `None` when there's nothing to wait for, see `start`.
+    fn spawn_guarded(
+        mut self,
+        status: Option<watch::Sender<Status>>,
+    ) -> Result<(WriterGuard, Option<Ready>)> {
+        self.status = status.or(self.status);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+
+        let Some((_, ready)) = self.start(Some(shutdown_rx), Some(done_tx))?
         else {
+            return Ok((WriterGuard::default(), None));
+        };
+
+        Ok((
+            WriterGuard {
+                shutdown: Some(shutdown_tx),
+                done: Some(done_rx),
+            },
+            Some(ready),
+        ))
+    }
+
+    // Where records go for `config`, `None` without a remote.
+    fn target(
+        config: &LayerConfig,
+        source: Option<&SourceProcess>,
+    ) -> Result<Option<(EndpointAddr, Claims)>> {
+        let Some(remote) = &config.remote else {
+            return Ok(None);
+        };
+        let addr = remote.check()?;
+
+        let mut labels = detect_labels(&config.detect_labels, source);
+        labels.extend(config.labels.clone());
+
+        let identity = Claims::builder()
+            .maybe_display_name(config.display_name.clone())
+            .maybe_source(source.cloned())
+            .labels(labels)
+            .build();
+
+        Ok(Some((addr, identity)))
+    }
+
+    // Each source process gets its own key, so that writers tapping different
+    // processes on the same host don't collide. The lock is held until the
+    // driver stops, see `KeySource::load_writer`.
+    fn secret_key(&self) -> Result<(iroh::SecretKey, Option<config::KeyLock>)> {
+        let key_name = self.source.as_ref().map_or_else(
+            || SourceProcess::default().name,
+            |source| source.name.clone(),
+        );
+
+        self.config.key.load_writer(&key_name)
+    }
+
+    // Sources that were only given a name (pid 0) can't be sampled.
+    fn sample_pid(&self) -> Option<u32> {
+        self.source
+            .as_ref()
+            .map(|source| source.pid)
+            .filter(|pid| *pid != 0)
+    }
+
+    // Starts the driver on the writer's thread, `None` when there's no remote
+    // to send to (and nothing to wait for one). `done` is dropped once the
+    // driver has stopped.
+    fn start(
+        self,
+        shutdown: Option<oneshot::Receiver<()>>,
+        done: Option<std::sync::mpsc::Sender<()>>,
+    ) -> Result<Option<(JoinHandle<()>, Ready)>> {
+        if self.config.remote.is_none() && !self.reload {
             tracing::warn!("disabling writer, no address configured");
-            return Ok(tokio::spawn(async {}));
+            return Ok(None);
+        }
+
+        // Checked up front, a bad remote is reported to the caller.
+        let target = Self::target(&self.config, self.source.as_ref())?;
+
+        let opts = EmitterOpts::builder()
+            .maybe_resource_interval(self.config.resource_interval)
+            .build();
+
+        let sample_pid = self.sample_pid();
+        let (secret_key, key_lock) = self.secret_key()?;
+
+        let redactor = Redactor::new(&self.config.redact)?;
+        let Self {
+            rx,
+            config,
+            source,
+            reload,
+            layer,
+            output,
+            status,
+        } = self;
+
+        let (ready_tx, ready_rx) = oneshot::channel();
+
+        // The endpoint has to be bound on the writer's thread, iroh spawns its
+        // background tasks onto whatever runtime is current.
+        let handle = suppress::spawn(
+            "laminar-writer",
+            output,
+            async move {
+                let _done = done;
+                let _key_lock = key_lock;
+                let mut shutdown = shutdown;
+                let mut ready_tx = Some(ready_tx);
+
+                tracing::info!(config = ?config, "starting writer");
+
+                let network = config.network.clone();
+                let mut configs = reload
+                    .then(|| reload::watch(None, config, reload::INTERVAL));
+
+                let (addr, identity) = if let Some(target) = target {
+                    target
+                } else {
+                    // Nothing to bind yet, don't keep the caller waiting.
+                    if let Some(ready) = ready_tx.take() {
+                        ready.send(Ok(())).ok();
+                    }
+                    tracing::info!("no remote configured, waiting for one");
+
+                    let Some(target) = wait_for_remote(
+                        configs.as_mut(),
+                        shutdown.as_mut(),
+                        layer.as_deref(),
+                        source.as_ref(),
+                    )
+                    .await
+                    else {
+                        return;
+                    };
+
+                    target
+                };
+
+                let endpoint = match network
+                    .endpoint()
+                    .secret_key(secret_key)
+                    .bind()
+                    .await
+                {
+                    Ok(endpoint) => endpoint,
+                    Err(e) => {
+                        if let Some(ready) = ready_tx.take() {
+                            ready.send(Err(e)).ok();
+                        } else {
+                            tracing::error!(err = ?e, "unable to bind");
+                        }
+                        return;
+                    }
+                };
+
+                if let Some(ready) = ready_tx.take() {
+                    ready.send(Ok(())).ok();
+                }
+
+                let current = Target::new(addr.clone(), &identity).ok();
+                let mut driver = sink::Client::builder()
+                    .endpoint(endpoint)
+                    .opts(opts)
+                    .maybe_sample_pid(sample_pid)
+                    .maybe_shutdown(shutdown)
+                    .maybe_status(status)
+                    .address(addr)
+                    .identity(identity)
+                    .build()
+                    .into_driver();
+
+                if let Some(configs) = configs {
+                    let (targets, updates) = watch::channel(current);
+                    tokio::spawn(follow(configs, targets, layer, source));
+                    driver = driver.follow(updates);
+                }
+
+                driver
+                    .run(rx, move |record| match &redactor {
+                        Some(redactor) => redactor.redact(record),
+                        None => record,
+                    })
+                    .await;
+            }
+            .in_current_span(),
+        )?;
+
+        Ok(Some((handle, ready_rx)))
+    }
+}
+
+// Applies a reloaded config to the layer, returns where records should go
+// now.
+fn apply(
+    config: &LayerConfig,
+    layer: Option<&LayerState>,
+    source: Option<&SourceProcess>,
+) -> Option<(EndpointAddr, Claims)> {
+    if let Some(layer) = layer {
+        layer.apply(config);
+    }
+
+    Writer::target(config, source)
+        .inspect_err(|e| {
+            tracing::warn!(err = ?e, "unable to use the reloaded remote");
+        })
+        .ok()
+        .flatten()
+}
+
+// `None` when the writer was shut down first.
+async fn wait_for_remote(
+    configs: Option<&mut watch::Receiver<LayerConfig>>,
+    mut shutdown: Option<&mut oneshot::Receiver<()>>,
+    layer: Option<&LayerState>,
+    source: Option<&SourceProcess>,
+) -> Option<(EndpointAddr, Claims)> {
+    let configs = configs?;
+
+    loop {
+        let stopped = async {
+            match shutdown.as_mut() {
+                Some(rx) => rx.await.ok(),
+                None => future::pending().await,
+            }
         };
 
-        let opts = EmitterOpts::default();
-        let endpoint = Endpoint::builder().bind().in_current_span().await?;
-
-        let driver = sink::Client::builder()
-            .endpoint(endpoint)
-            .opts(opts.clone())
-            .address(addr)
-            .identity(
-                Claims::builder()
-                    .maybe_display_name(self.config.display_name)
-                    .maybe_source(self.source)
-                    .build(),
-            )
-            .build()
-            .into_driver();
-
-        if !tracing::dispatcher::get_default(|d| {
-            d.enabled(span.metadata().expect("just constructed"))
-        }) {
-            panic!(
-                "must be run within a span that has the drop target. Is there \
-                 a subscriber registered? Does it allow {DROP_TARGET}=error?"
-            )
+        tokio::select! {
+            _ = stopped => return None,
+            changed = configs.changed() => changed.ok()?,
+        }
+
+        let config = configs.borrow_and_update().clone();
+        if let Some(target) = apply(&config, layer, source) {
+            return Some(target);
+        }
+    }
+}
+
+// Moves the driver along with the config, until it stops being watched. The
+// layer stops sending when the remote is removed, the driver stays where it
+// is until there's a new one.
+async fn follow(
+    mut configs: watch::Receiver<LayerConfig>,
+    targets: watch::Sender<Option<Target>>,
+    layer: Option<Arc<LayerState>>,
+    source: Option<SourceProcess>,
+) {
+    while configs.changed().await.is_ok() {
+        let config = configs.borrow_and_update().clone();
+
+        let Some(target) = apply(&config, layer.as_deref(), source.as_ref())
+            .and_then(|(addr, identity)| Target::new(addr, &identity).ok())
+        else {
+            continue;
+        };
+
+        targets.send_if_modified(|current| {
+            if current.as_ref() == Some(&target) {
+                return false;
+            }
+
+            *current = Some(target);
+            true
+        });
+    }
+}
+
+// Keeps the writer started by `StreamLayerBuilder::spawn` running. When
+// dropped, buffered records are flushed, waiting at most a few seconds.
+#[derive(Debug, Default)]
+pub struct WriterGuard {
+    shutdown: Option<oneshot::Sender<()>>,
+    done: Option<std::sync::mpsc::Receiver<()>>,
+}
+
+impl Drop for WriterGuard {
+    fn drop(&mut self) {
+        let Some(done) = self.done.take() else {
+            return;
+        };
+
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
         }
 
-        Ok(tokio::spawn(driver.run(self.rx).in_current_span()))
+        // The sender is dropped when the driver stops, so this doesn't wait
+        // for the full timeout unless the flush is stuck.
+        if done.recv_timeout(Writer::FLUSH_TIMEOUT)
+            == Err(RecvTimeoutError::Timeout)
+        {
+            tracing::warn!("timed out flushing writer");
+        }
     }
 }
 
 struct DropCallsite;
 
+// Set on spans with `laminar.capture = true` and all of their descendants.
+// Everything in them is sent, regardless of `LayerConfig::filter` or the
+// recorder.
+struct CaptureScope;
+
+#[derive(Default)]
+struct CaptureField(bool);
+
+impl Visit for CaptureField {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == CAPTURE_FIELD {
+            self.0 = value;
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == CAPTURE_FIELD {
+            self.0 = format!("{value:?}") == "true";
+        }
+    }
+}
+
+// Everything needed to send a `Kind::SpanClose` record. Busy/idle time is
+// tracked the same way `tracing_subscriber::fmt` does for `FmtSpan::CLOSE`.
+struct SpanState {
+    parent_context: Option<SpanContext>,
+    // All of the span's fields, kept for `SpanScope` when
+    // `LayerConfig::span_fields` is enabled.
+    attrs: Option<JsonFields>,
+    start: i64,
+    created: Instant,
+    last: Instant,
+    busy: Duration,
+    idle: Duration,
+    // Values set via `Span::record` after the span was created.
+    fields: JsonFields,
+    follows_from: Vec<SpanContext>,
+}
+
+impl SpanState {
+    fn new(
+        parent_context: Option<SpanContext>,
+        attrs: Option<JsonFields>,
+    ) -> Self {
+        let now = Instant::now();
+
+        Self {
+            parent_context,
+            attrs,
+            start: api::now(),
+            created: now,
+            last: now,
+            busy: Duration::ZERO,
+            idle: Duration::ZERO,
+            fields: JsonFields::new(),
+            follows_from: Vec::new(),
+        }
+    }
+
+    fn enter(&mut self) {
+        let now = Instant::now();
+        self.idle += now.saturating_duration_since(self.last);
+        self.last = now;
+    }
+
+    fn exit(&mut self) {
+        let now = Instant::now();
+        self.busy += now.saturating_duration_since(self.last);
+        self.last = now;
+    }
+
+    fn timing(&self) -> SpanTiming {
+        let now = Instant::now();
+        let nanos =
+            |d: Duration| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX);
+
+        SpanTiming {
+            start: self.start,
+            duration_ns: nanos(now.saturating_duration_since(self.created)),
+            busy_ns: nanos(self.busy),
+            idle_ns: nanos(
+                self.idle + now.saturating_duration_since(self.last),
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct StreamLayerBuilder {
     config: Option<LayerConfig>,
+    reload: bool,
 }
 
 impl StreamLayerBuilder {
+    // Follow changes to the config file, see `reload`. Off unless enabled
+    // here or with `LayerConfig::reload`, it means polling the file for as
+    // long as the writer runs.
+    #[must_use]
+    pub const fn reload(mut self, reload: bool) -> Self {
+        self.reload = reload;
+        self
+    }
+
     #[must_use]
     pub fn config(mut self, cfg: LayerConfig) -> Self {
         self.config = Some(cfg);
@@ -108,32 +546,86 @@ impl StreamLayerBuilder {
             Some(cfg) => cfg,
             None => Config::load()?.layer(),
         };
+        let reload = self.reload || config.reload;
+
+        let state = Arc::new(LayerState::new(&config)?);
 
         let (tx, rx) = emitter(EmitterOpts::default().buffer_size);
 
+        // Both follow `remote` when reloading, like the layer they're only
+        // quiet while it's disabled.
+        let running = config.remote.is_some() || reload;
+
+        let (status, panic_hook) = if config.capture_panics && running {
+            let (status, acks) = watch::channel(Status::default());
+            let hook = panic_hook::install(&tx, acks, &state.disabled);
+            (Some(status), Some(hook))
+        } else {
+            (None, None)
+        };
+
+        let capture_output = config.capture_output && running;
+        #[cfg(unix)]
+        let output = capture_output
+            .then(|| capture::install(&tx, &state.disabled))
+            .transpose()?
+            .map(Arc::new);
+        #[cfg(not(unix))]
+        let output = capture_output
+            .then(|| tracing::warn!("output capture is only supported on unix"))
+            .and(None);
+
         Ok((
             StreamLayer {
                 tx,
-                disabled: config.remote.is_none(),
+                state: state.clone(),
+                span_fields: config.span_fields,
+                error_backtraces: config.error_backtraces,
+                sampler: Sampler::new(config.sampling.clone()),
+                recorder: Recorder::new(&config.recorder),
+                _panic_hook: panic_hook,
+            },
+            Writer {
+                layer: Some(state),
+                output,
+                status,
+                ..Writer::builder()
+                    .rx(rx)
+                    .config(config)
+                    .source(SourceProcess::default())
+                    .reload(reload)
+                    .build()
             },
-            Writer::builder()
-                .rx(rx)
-                .config(config)
-                .source(SourceProcess::default())
-                .build(),
         ))
     }
+
+    // Builds the layer and starts its writer in the background, no runtime
+    // required. Keep the guard around for as long as the layer is in use.
+    pub fn spawn(self) -> Result<(StreamLayer, WriterGuard)> {
+        let (layer, writer) = self.build()?;
+
+        Ok((layer, writer.spawn()?))
+    }
 }
 
-// Warning: if the writer and reader are in the same process, you must exempt
-// the reader by running it in a DROP_TARGET span.
+// Warning: if the writer and reader are in the same process, the reader should
+// be run in a DROP_TARGET span. Iroh's own targets are always dropped, but
+// anything else it calls into (eg DNS) would be recorded.
 //
 // TODO: I want this to work in WASM environments. The network
 // stack is going to need to be pluggable and most of tokio won't be usable.
 #[derive(Debug)]
 pub struct StreamLayer {
-    disabled: bool,
+    // Whether there's a remote and the per-layer filter, see
+    // `LayerConfig::filter`. Both follow the config when reloading.
+    state: Arc<LayerState>,
+    span_fields: bool,
+    error_backtraces: bool,
+    sampler: Option<Sampler>,
+    recorder: Option<Recorder>,
     tx: EmitterSender<Record>,
+    // Panics are sent to the writer for as long as the layer is around.
+    _panic_hook: Option<panic_hook::Registration>,
 }
 
 impl StreamLayer {
@@ -143,7 +635,10 @@ impl StreamLayer {
 
     #[must_use]
     pub const fn builder() -> StreamLayerBuilder {
-        StreamLayerBuilder { config: None }
+        StreamLayerBuilder {
+            config: None,
+            reload: false,
+        }
     }
 
     fn send(&self, record: Record) {
@@ -151,6 +646,8 @@ impl StreamLayer {
             return;
         }
 
+        let _suppress = suppress::enter();
+
         if self.tx.send(record).is_err() {
             tracing::debug!("unable to send record");
         }
@@ -158,31 +655,59 @@ impl StreamLayer {
 
     #[must_use]
     pub fn disabled(&self) -> bool {
-        self.disabled || self.tx.is_closed()
+        self.state.disabled() || self.tx.is_closed()
+    }
+
+    // A `log::Log` implementation that shares this layer's emitter.
+    #[cfg(feature = "log")]
+    #[must_use]
+    pub fn logger(&self) -> logger::Logger {
+        logger::Logger {
+            disabled: self.state.disabled.clone(),
+            tx: self.tx.clone(),
+        }
+    }
+
+    fn enabled<S>(
+        &self,
+        metadata: &tracing::Metadata<'_>,
+        ctx: &Context<'_, S>,
+    ) -> bool
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        self.state
+            .filter()
+            .as_ref()
+            .is_none_or(|filter| Filter::enabled(filter, metadata, ctx))
     }
 }
 
+// Spans that were filtered out never get a `SpanContext`. This behaves like
+// `Filtered` does for per-layer filters, the filtered spans are skipped and
+// children attach to the closest ancestor that was recorded.
+fn recorded_parent<'a, S>(span: &SpanRef<'a, S>) -> Option<SpanRef<'a, S>>
+where
+    S: LookupSpan<'a>,
+{
+    span.scope()
+        .skip(1)
+        .find(|parent| parent.extensions().get::<SpanContext>().is_some())
+}
+
 // It is important that spans/events generated as part of the laminar layer
-// itself are dropped. They multiply exponentially otherwise. To do the
-// dropping, we:
-//
-// - Look for a span that has target `laminar_stream::drop`.
-// - Add an extension `DropCallsite` to the span.
-// - Look for a parent span that has the `DropCallsite` extension.
-//
-// In either case, we drop the span. For events, we look for the parent span and
-// drop it.
+// itself are dropped. They multiply exponentially otherwise. A span or event is
+// dropped when:
 //
-// Note that this is not 100% effective.
+// - It happens on the writer's thread, or while the layer is sending a record.
+//   See `suppress` for the details.
+// - The target belongs to laminar's networking stack (iroh, quinn, ...).
+// - The span has target `laminar_stream::drop`, this is how other code (eg a
+//   reader in the same process) opts out.
+// - A parent span was dropped. These get a `DropCallsite` extension.
 //
-// - Spans do not cross async boundaries by default. The best practice is to use
-//   `instrument` or `in_current_span`. Unfortunately, because this is up to the
-//   library author, it doesn't always happen.
-// - Some library authors explicitly remove the parent span entirely (iroh's
-//   `RemoteStateActor`) for example.
-// - The tokio runtime starts up outside of the stream layer itself and is
-//   shared.
-// - Some events have the span explicitly removed.
+// Spans that opt into capturing get a `CaptureScope` extension the same way,
+// it is inherited by every child span.
 impl<S> Layer<S> for StreamLayer
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
@@ -197,18 +722,39 @@ where
             return;
         }
 
+        if let Some(filter) = self.state.filter().as_ref() {
+            Filter::on_new_span(filter, attrs, id, ctx.clone());
+        }
+
         let span = ctx.span(id).expect("span exists");
         let will_drop = span
             .parent()
             .is_some_and(|p| p.extensions().get::<DropCallsite>().is_some());
 
-        if attrs.metadata().target() == DROP_TARGET || will_drop {
+        let target = attrs.metadata().target();
+        if target == DROP_TARGET || suppress::is_suppressed(target) || will_drop
+        {
             span.extensions_mut().insert(DropCallsite);
             metrics::counter!("layer.drop.span").increment(1);
 
             return;
         }
 
+        let captured = span
+            .parent()
+            .is_some_and(|p| p.extensions().get::<CaptureScope>().is_some())
+            || {
+                let mut capture = CaptureField::default();
+                attrs.record(&mut capture);
+                capture.0
+            };
+
+        if captured {
+            span.extensions_mut().insert(CaptureScope);
+        } else if !self.enabled(attrs.metadata(), &ctx) {
+            return;
+        }
+
         // I'm spot checking that the networking spans are *mostly* being
         // dropped in the tests. To keep from having a cardinality explosion, we
         // only do this for running tests.
@@ -229,7 +775,171 @@ where
 
         metrics::counter!("layer.span").increment(1);
 
-        self.send(Record::from_span(attrs, id));
+        // A `traceparent` field takes precedence, that's how spans get linked
+        // to a parent in another process.
+        let mut remote = propagation::RemoteParent::default();
+        attrs.record(&mut remote);
+
+        let parent = recorded_parent(&span);
+        let parent_context = remote.0.or_else(|| {
+            parent
+                .as_ref()
+                .and_then(|p| p.extensions().get::<SpanContext>().copied())
+        });
+        let context = parent_context
+            .as_ref()
+            .map_or_else(SpanContext::root, SpanContext::child);
+
+        {
+            let mut extensions = span.extensions_mut();
+            extensions.insert(context);
+            extensions.insert(SpanState::new(
+                parent_context,
+                self.span_fields.then(|| visit_fields(attrs)),
+            ));
+        }
+
+        let trace = TraceId {
+            span: Some(id.into_u64()),
+            parent: parent.map(|p| p.id().into_u64()),
+            context: Some(context),
+            parent_context,
+            follows_from: Vec::new(),
+        };
+
+        self.send(Record::from_span(attrs, trace));
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        ctx: Context<'_, S>,
+    ) {
+        if let Some(filter) = self.state.filter().as_ref() {
+            Filter::on_record(filter, id, values, ctx.clone());
+        }
+
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        // Capturing can be turned on after the fact, eg once the customer a
+        // request is for is known. Only spans created afterwards inherit it.
+        let mut capture = CaptureField::default();
+        values.record(&mut capture);
+        if capture.0 {
+            span.extensions_mut().insert(CaptureScope);
+        }
+
+        if let Some(state) = span.extensions_mut().get_mut::<SpanState>() {
+            let fields = visit_fields(values);
+
+            if let Some(attrs) = state.attrs.as_mut() {
+                attrs.extend(fields.clone());
+            }
+
+            state.fields.extend(fields);
+        }
+    }
+
+    fn on_follows_from(
+        &self,
+        id: &tracing::span::Id,
+        follows: &tracing::span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(follows) = ctx
+            .span(follows)
+            .and_then(|span| span.extensions().get::<SpanContext>().copied())
+        else {
+            return;
+        };
+
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        if let Some(state) = span.extensions_mut().get_mut::<SpanState>() {
+            state.follows_from.push(follows);
+        }
+    }
+
+    fn on_enter(&self, id: &tracing::span::Id, ctx: Context<'_, S>) {
+        if let Some(filter) = self.state.filter().as_ref() {
+            Filter::on_enter(filter, id, ctx.clone());
+        }
+
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        if let Some(state) = span.extensions_mut().get_mut::<SpanState>() {
+            state.enter();
+        }
+    }
+
+    fn on_exit(&self, id: &tracing::span::Id, ctx: Context<'_, S>) {
+        if let Some(filter) = self.state.filter().as_ref() {
+            Filter::on_exit(filter, id, ctx.clone());
+        }
+
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        if let Some(state) = span.extensions_mut().get_mut::<SpanState>() {
+            state.exit();
+        }
+    }
+
+    fn on_close(&self, id: tracing::span::Id, ctx: Context<'_, S>) {
+        if let Some(filter) = self.state.filter().as_ref() {
+            Filter::on_close(filter, id.clone(), ctx.clone());
+        }
+
+        if self.disabled() {
+            return;
+        }
+
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+
+        // Dropped spans never get any state, so they're skipped here as well.
+        let (state, context) = {
+            let mut extensions = span.extensions_mut();
+            let Some(state) = extensions.remove::<SpanState>() else {
+                return;
+            };
+
+            (state, extensions.get_mut::<SpanContext>().copied())
+        };
+
+        metrics::counter!("layer.span.close").increment(1);
+
+        let parent = recorded_parent(&span);
+
+        if let (Some(recorder), Some(context), None) =
+            (&self.recorder, context, &parent)
+        {
+            recorder.discard(context.trace_id);
+        }
+
+        let trace = TraceId {
+            span: Some(id.into_u64()),
+            parent: parent.map(|p| p.id().into_u64()),
+            context,
+            parent_context: state.parent_context,
+            follows_from: state.follows_from.clone(),
+        };
+
+        self.send(Record::from_span_close(
+            span.metadata(),
+            trace,
+            state.timing(),
+            state.fields,
+        ));
     }
 
     fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
@@ -237,16 +947,31 @@ where
             return;
         }
 
-        let will_drop = ctx
-            .lookup_current()
-            .is_some_and(|p| p.extensions().get::<DropCallsite>().is_some());
+        let (will_drop, captured) =
+            ctx.event_span(event).map_or((false, false), |p| {
+                let extensions = p.extensions();
+
+                (
+                    extensions.get::<DropCallsite>().is_some(),
+                    extensions.get::<CaptureScope>().is_some(),
+                )
+            });
 
-        if will_drop {
+        if suppress::is_suppressed(event.metadata().target()) || will_drop {
             metrics::counter!("layer.drop.event").increment(1);
 
             return;
         }
 
+        if !captured
+            && (!self.enabled(event.metadata(), &ctx)
+                || self.state.filter().as_ref().is_some_and(|filter| {
+                    !Filter::event_enabled(filter, event, &ctx)
+                }))
+        {
+            return;
+        }
+
         // I'm spot checking that the networking events are *mostly* being
         // dropped in the tests. To keep from having a cardinality explosion, we
         // only do this for running tests.
@@ -265,9 +990,82 @@ where
             );
         }
 
+        // Events kept by the recorder aren't sampled, they might never be
+        // sent at all.
+        let recorded = !captured
+            && self
+                .recorder
+                .as_ref()
+                .is_some_and(|recorder| recorder.records(event.metadata()));
+
+        let suppressed = match &self.sampler {
+            Some(sampler) if !recorded => {
+                match sampler.sample(event.metadata()) {
+                    Some(suppressed) => suppressed,
+                    None => return,
+                }
+            }
+            _ => 0,
+        };
+
         metrics::counter!("layer.event").increment(1);
 
-        self.send(Record::from_event(event));
+        // This resolves the contextual parent as well as explicit ones.
+        let scope = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .filter(|span| {
+                        span.extensions().get::<SpanContext>().is_some()
+                    })
+                    .map(|span| {
+                        let extensions = span.extensions();
+
+                        SpanScope {
+                            id: span.id().into_u64(),
+                            name: span.name(),
+                            context: extensions.get::<SpanContext>().copied(),
+                            fields: extensions
+                                .get::<SpanState>()
+                                .and_then(|state| state.attrs.clone()),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let mut record = Record::from_event(event, &scope);
+
+        // Records are only annotated when something was actually dropped.
+        if suppressed > 0 {
+            record.annotate("suppressed", suppressed.into());
+        }
+
+        if self.error_backtraces
+            && *event.metadata().level() == tracing::Level::ERROR
+        {
+            record.annotate(
+                "backtrace",
+                std::backtrace::Backtrace::force_capture()
+                    .to_string()
+                    .into(),
+            );
+        }
+
+        if let Some(recorder) = &self.recorder {
+            if recorded {
+                recorder.push(record);
+                return;
+            }
+
+            if recorder.triggered(event) {
+                for earlier in recorder.flush(&record) {
+                    self.send(earlier);
+                }
+            }
+        }
+
+        self.send(record);
     }
 }
 
@@ -280,10 +1078,48 @@ mod test {
     use laminar_testing::Telemetry;
     use serde::Serialize;
     use tokio::{sync::broadcast, time};
+    use tracing::subscriber::DefaultGuard;
     use tracing_subscriber::{filter::LevelFilter, prelude::*};
 
     use super::*;
-    use crate::sink::SinkDriver;
+    use crate::{config::KeySource, sink::SinkDriver};
+
+    // A throwaway key and, unless the test has its own, a remote that is never
+    // dialed.
+    fn layer_config(config: LayerConfig) -> LayerConfig {
+        LayerConfig {
+            key: KeySource::Ephemeral,
+            remote: config.remote.or_else(|| {
+                let key = SecretKey::from_bytes(&rand::random::<[u8; 32]>());
+                Some(key.public().into())
+            }),
+            ..config
+        }
+    }
+
+    // Installs the layer as the default subscriber until the guard is dropped.
+    // The writer isn't started, what the layer emits is read with `records`.
+    fn subscribe(
+        config: LayerConfig,
+        level: LevelFilter,
+    ) -> Result<(DefaultGuard, Writer)> {
+        let (layer, writer) = StreamLayer::builder()
+            .config(layer_config(config))
+            .build()?;
+        let subscriber = tracing_subscriber::registry()
+            .with(level)
+            .with(layer)
+            .set_default();
+
+        Ok((subscriber, writer))
+    }
+
+    // Everything emitted so far.
+    fn records(writer: &mut Writer) -> Vec<Record> {
+        std::iter::from_fn(|| writer.rx.try_recv().ok())
+            .filter_map(Arc::into_inner)
+            .collect()
+    }
 
     // TODO: need a multi-threaded test
     #[tokio::test]
@@ -302,7 +1138,12 @@ mod test {
         tracing::info!("{}", keypair.public());
 
         let (layer, writer) = StreamLayer::builder()
-            .config(LayerConfig::builder().remote(keypair.public()).build())
+            .config(
+                LayerConfig::builder()
+                    .key(KeySource::Ephemeral)
+                    .remote(keypair.public())
+                    .build(),
+            )
             .build()?;
 
         #[cfg(feature = "test_pretty")]
@@ -377,11 +1218,97 @@ mod test {
         Ok(())
     }
 
+    // A layer without a remote picks one up from a reloaded config, later
+    // changes move the driver along.
+    #[tokio::test]
+    async fn test_reload() -> Result<()> {
+        let state = LayerState::new(
+            &LayerConfig::builder().key(KeySource::Ephemeral).build(),
+        )?;
+        let (configs, mut rx) = watch::channel(
+            LayerConfig::builder().key(KeySource::Ephemeral).build(),
+        );
+
+        let first = SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public();
+        configs.send(
+            LayerConfig::builder()
+                .key(KeySource::Ephemeral)
+                .remote(first)
+                .build(),
+        )?;
+
+        let (addr, _) =
+            wait_for_remote(Some(&mut rx), None, Some(&state), None)
+                .await
+                .expect("a remote");
+        assert_eq!(addr.id, first);
+        assert!(!state.disabled());
+
+        let (targets, mut updates) = watch::channel(None);
+        let task = tokio::spawn(follow(rx, targets, Some(state.into()), None));
+
+        let second =
+            SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public();
+        configs.send(
+            LayerConfig::builder()
+                .key(KeySource::Ephemeral)
+                .remote(second)
+                .build(),
+        )?;
+        time::timeout(Duration::from_secs(1), updates.changed()).await??;
+        assert_eq!(
+            updates.borrow_and_update().as_ref().map(|t| t.addr.id),
+            Some(second)
+        );
+
+        // Only the filter changed, there's nothing for the driver to do.
+        configs.send(
+            LayerConfig::builder()
+                .key(KeySource::Ephemeral)
+                .remote(second)
+                .filter("debug")
+                .build(),
+        )?;
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(!updates.has_changed()?);
+
+        drop(configs);
+        time::timeout(Duration::from_secs(1), task).await??;
+
+        Ok(())
+    }
+
+    // Polling the config file is opt-in, from the builder or the config.
+    #[test]
+    fn test_reload_opt_in() -> Result<()> {
+        let config = LayerConfig::builder().key(KeySource::Ephemeral).build();
+
+        let (_, writer) =
+            StreamLayer::builder().config(config.clone()).build()?;
+        assert!(!writer.reload);
+
+        let (_, writer) = StreamLayer::builder()
+            .config(config.clone())
+            .reload(true)
+            .build()?;
+        assert!(writer.reload);
+
+        let (_, writer) = StreamLayer::builder()
+            .config(LayerConfig {
+                reload: true,
+                ..config
+            })
+            .build()?;
+        assert!(writer.reload);
+
+        Ok(())
+    }
+
     // Verify that everything is disabled when a remote is not configured.
     #[tokio::test]
     async fn test_disabled() -> Result<()> {
         let (layer, writer) = StreamLayer::builder()
-            .config(LayerConfig::builder().build())
+            .config(LayerConfig::builder().key(KeySource::Ephemeral).build())
             .build()?;
 
         time::timeout(Duration::from_millis(10), writer.run()).await??;
@@ -391,6 +1318,87 @@ mod test {
         Ok(())
     }
 
+    // With output captured, the writer's own warnings still make it to the
+    // terminal but don't come back as records.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_capture_writer() -> Result<()> {
+        use std::{
+            io::{BufRead, BufReader},
+            os::fd::AsRawFd,
+        };
+
+        let (tx, mut captured) = emitter(100);
+        let (terminal, fake) = std::io::pipe()?;
+        let original = capture::redirect(
+            fake.as_raw_fd(),
+            "stderr",
+            tx,
+            Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        )?;
+
+        // Stands in for the host's formatter, which writes to the redirected
+        // stderr.
+        let fake = Arc::new(fake);
+        let _subscriber = tracing_subscriber::fmt()
+            .with_writer(fake.clone())
+            .with_ansi(false)
+            .set_default();
+
+        // Nothing is listening there, so the driver warns about it.
+        let peer = config::Peer::builder()
+            .key(SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public())
+            .addrs(vec![(std::net::Ipv4Addr::LOCALHOST, 9).into()])
+            .build();
+        let config = LayerConfig::builder()
+            .key(KeySource::Ephemeral)
+            .remote(config::Remote::Peer {
+                name: "closed".to_string(),
+                peer,
+            })
+            .network(
+                config::NetworkConfig::builder()
+                    .mode(config::NetworkMode::Local)
+                    .build(),
+            )
+            .build();
+
+        let (_, rx) = emitter(10);
+        let writer = Writer {
+            output: Some(Arc::new(original)),
+            ..Writer::builder().rx(rx).config(config).build()
+        };
+        let _handle = writer.run().await?;
+
+        tracing::warn!("from the app");
+
+        // A thread instead of `spawn_blocking`, the read never finishes when
+        // the warning doesn't show up.
+        let (lines_tx, lines_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(terminal).lines().map_while(Result::ok) {
+                if line.contains("failed to connect") {
+                    lines_tx.send(line).ok();
+                }
+            }
+        });
+        lines_rx.recv_timeout(Duration::from_secs(15))?;
+
+        // Records go out right after the line is written to the terminal.
+        time::sleep(Duration::from_millis(100)).await;
+        let messages = std::iter::from_fn(|| captured.try_recv().ok())
+            .map(|record| record.message.clone())
+            .collect::<Vec<_>>();
+
+        assert!(messages.iter().any(|m| m.contains("from the app")));
+        assert!(
+            !messages.iter().any(|m| m.contains("failed to connect")),
+            "{messages:?}"
+        );
+
+        Ok(())
+    }
+
     // Check to make sure the writer continues to run, even when the driver is
     // unable to connect. Events should continue to be sent.
     #[tokio::test]
@@ -401,7 +1409,12 @@ mod test {
         tracing::info!("{}", keypair.public());
 
         let (layer, writer) = StreamLayer::builder()
-            .config(LayerConfig::builder().remote(keypair.public()).build())
+            .config(
+                LayerConfig::builder()
+                    .key(KeySource::Ephemeral)
+                    .remote(keypair.public())
+                    .build(),
+            )
             .build()?;
 
         let handle = writer.run().await?;
@@ -413,13 +1426,604 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_propagation() -> Result<()> {
+        let (_subscriber, mut writer) =
+            subscribe(LayerConfig::default(), LevelFilter::DEBUG)?;
+
+        let remote = SpanContext::root();
+        let mut headers = Vec::new();
+
+        {
+            let _request = tracing::info_span!(
+                "request",
+                traceparent = %remote,
+            )
+            .entered();
+            let _inner = tracing::info_span!("inner").entered();
+
+            tracing::info!("inside");
+            propagation::inject(|k, v| headers.push((k.to_string(), v)));
+        }
+
+        let mut next = || -> TraceId {
+            Arc::into_inner(writer.rx.try_recv().expect("record"))
+                .expect("single owner")
+                .trace
+                .expect("has trace")
+        };
+
+        let request = next();
+        let request_ctx = request.context.expect("span has context");
+        assert_eq!(request.parent_context, Some(remote));
+        assert_eq!(request_ctx.trace_id, remote.trace_id);
+
+        let inner = next();
+        let inner_ctx = inner.context.expect("span has context");
+        assert_eq!(inner.parent_context, Some(request_ctx));
+        assert_eq!(inner_ctx.trace_id, remote.trace_id);
+
+        let event = next();
+        assert_eq!(event.context, Some(inner_ctx));
+
+        let extracted = propagation::extract(|k| {
+            headers
+                .iter()
+                .find(|(name, _)| name == k)
+                .map(|(_, v)| v.as_str())
+        });
+        assert_eq!(extracted, Some(inner_ctx));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_span_close() -> Result<()> {
+        let (_subscriber, mut writer) =
+            subscribe(LayerConfig::default(), LevelFilter::DEBUG)?;
+
+        let cause = tracing::info_span!("cause");
+        let span = tracing::info_span!("work", result = tracing::field::Empty);
+        span.follows_from(&cause);
+
+        span.in_scope(|| std::thread::sleep(Duration::from_millis(5)));
+        span.record("result", 42);
+        drop(span);
+
+        let records = records(&mut writer);
+
+        let close = records
+            .iter()
+            .find(|r| matches!(r.kind, Kind::SpanClose))
+            .expect("close record");
+        let cause = records
+            .iter()
+            .find(|r| r.message == "cause")
+            .and_then(|r| r.trace.as_ref()?.context)
+            .expect("cause context");
+
+        let timing = close.timing.as_ref().expect("has timing");
+        let trace = close.trace.as_ref().expect("has trace");
+
+        assert_eq!(close.message, "work");
+        assert!(timing.busy_ns >= 5_000_000);
+        assert!(timing.duration_ns >= timing.busy_ns + timing.idle_ns);
+        assert_eq!(trace.follows_from, vec![cause]);
+        assert_eq!(close.fields, r#"{"result":42}"#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_scope() -> Result<()> {
+        let (_subscriber, mut writer) = subscribe(
+            LayerConfig::builder().span_fields(true).build(),
+            LevelFilter::DEBUG,
+        )?;
+
+        let outer = tracing::info_span!("outer", user = "alice");
+        let inner = outer.in_scope(|| tracing::info_span!("inner"));
+        inner.in_scope(|| tracing::info!("contextual"));
+
+        let records = records(&mut writer);
+
+        let [outer, inner, event] = records.as_slice() else {
+            panic!("expected three records: {records:?}");
+        };
+
+        let outer_id = outer.trace.as_ref().and_then(|t| t.span);
+        let inner_trace = inner.trace.as_ref().expect("has trace");
+        let event_trace = event.trace.as_ref().expect("has trace");
+
+        assert_eq!(inner_trace.parent, outer_id);
+        assert_eq!(event_trace.parent, inner_trace.span);
+        assert_eq!(event_trace.context, inner_trace.context);
+
+        let fields: serde_json::Value = serde_json::from_str(&event.fields)?;
+        let spans = fields["tracing"]["spans"].as_array().expect("has spans");
+
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["name"], "outer");
+        assert_eq!(spans[0]["fields"]["user"], "alice");
+        assert_eq!(spans[1]["name"], "inner");
+        assert_eq!(
+            spans[1]["span_id"],
+            inner_trace.context.expect("has context").span_id_hex()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sampling() -> Result<()> {
+        let (_subscriber, mut writer) = subscribe(
+            LayerConfig::builder()
+                .sampling(
+                    sampling::SamplingConfig::builder()
+                        .first(1)
+                        .every(3)
+                        .build(),
+                )
+                .build(),
+            LevelFilter::DEBUG,
+        )?;
+
+        for i in 0..4 {
+            tracing::debug!(i, "hot loop");
+        }
+
+        let records = records(&mut writer)
+            .into_iter()
+            .map(|r| serde_json::from_str(&r.fields))
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+        let [first, next] = records.as_slice() else {
+            panic!("expected two records: {records:?}");
+        };
+
+        assert_eq!(first["i"], 0);
+        assert!(first["tracing"].get("suppressed").is_none());
+        assert_eq!(next["i"], 3);
+        assert_eq!(next["tracing"]["suppressed"], 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recorder() -> Result<()> {
+        let (_subscriber, mut writer) = subscribe(
+            LayerConfig::builder()
+                .recorder(
+                    recorder::RecorderConfig::builder()
+                        .level(Level::Info)
+                        .per_trace(true)
+                        .build(),
+                )
+                .build(),
+            LevelFilter::TRACE,
+        )?;
+
+        tracing::info_span!("ok").in_scope(|| {
+            tracing::debug!("discarded");
+        });
+        tracing::info_span!("failing").in_scope(|| {
+            tracing::trace!("before");
+            tracing::info!("sent");
+            tracing::error!("boom");
+        });
+        tracing::debug!("kept");
+        tracing::info!(laminar.trigger = true, "triggered");
+
+        let records = records(&mut writer)
+            .into_iter()
+            .filter(|r| matches!(r.kind, Kind::Event))
+            .collect::<Vec<_>>();
+
+        let messages = records
+            .iter()
+            .map(|r| r.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec!["sent", "before", "boom", "kept", "triggered"]
+        );
+
+        let retroactive = records
+            .iter()
+            .map(|r| {
+                serde_json::from_str::<serde_json::Value>(&r.fields)
+                    .map(|f| f["tracing"]["retroactive"] == true)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(retroactive, vec![false, true, false, true, false]);
+
+        Ok(())
+    }
+
+    // Only a true `laminar.trigger` sends what the recorder kept.
+    #[test]
+    fn test_recorder_trigger() -> Result<()> {
+        let (_subscriber, mut writer) = subscribe(
+            LayerConfig::builder()
+                .recorder(
+                    recorder::RecorderConfig::builder()
+                        .level(Level::Info)
+                        .build(),
+                )
+                .build(),
+            LevelFilter::TRACE,
+        )?;
+
+        tracing::debug!("kept");
+        tracing::info!(laminar.trigger = false, "not triggered");
+        tracing::info!(laminar.trigger = true, "triggered");
+
+        let messages = records(&mut writer)
+            .into_iter()
+            .filter(|r| matches!(r.kind, Kind::Event))
+            .map(|r| r.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["not triggered", "kept", "triggered"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter() -> Result<()> {
+        let (_subscriber, mut writer) = subscribe(
+            LayerConfig::builder().filter("warn,app=debug").build(),
+            LevelFilter::TRACE,
+        )?;
+
+        tracing::debug!(target: "app", "kept");
+        tracing::info!(target: "noisy", "filtered");
+        tracing::warn!(target: "noisy", "warning");
+
+        let outer = tracing::info_span!(target: "app", "outer");
+        let hidden =
+            outer.in_scope(|| tracing::info_span!(target: "noisy", "hidden"));
+        hidden.in_scope(|| tracing::trace!(target: "app", "too verbose"));
+        hidden.in_scope(|| tracing::debug!(target: "app", "nested"));
+
+        let records = records(&mut writer);
+
+        let messages = records
+            .iter()
+            .map(|r| r.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["kept", "warning", "outer", "nested"]);
+
+        // The filtered span is skipped, the event is attached to `outer`.
+        let outer = records[2].trace.as_ref().expect("has trace");
+        let nested = records[3].trace.as_ref().expect("has trace");
+        assert_eq!(nested.parent, outer.span);
+        assert_eq!(nested.context, outer.context);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_scope() -> Result<()> {
+        let (_subscriber, mut writer) = subscribe(
+            LayerConfig::builder().filter("warn").build(),
+            LevelFilter::TRACE,
+        )?;
+
+        tracing::debug!("filtered");
+
+        tracing::info_span!("request", laminar.capture = true).in_scope(|| {
+            tracing::debug!("captured");
+            tracing::trace_span!("db").in_scope(|| tracing::trace!("query"));
+        });
+
+        let late = tracing::info_span!(
+            "late",
+            laminar.capture = tracing::field::Empty
+        );
+        late.in_scope(|| tracing::debug!("before"));
+        late.record("laminar.capture", true);
+        late.in_scope(|| {
+            tracing::debug_span!("inner").in_scope(|| tracing::debug!("after"));
+        });
+
+        let messages = records(&mut writer)
+            .into_iter()
+            .filter(|r| !matches!(r.kind, Kind::SpanClose))
+            .map(|r| r.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec!["request", "captured", "db", "query", "inner", "after"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_suppressed() -> Result<()> {
+        let (_subscriber, mut writer) =
+            subscribe(LayerConfig::default(), LevelFilter::TRACE)?;
+
+        tracing::info!(target: "iroh::socket", "transport");
+        tracing::info!(target: "quinn_proto::connection", "transport");
+        tracing::info_span!(target: "iroh_relay::client", "relay")
+            .in_scope(|| tracing::info!(target: "hickory_proto", "lookup"));
+
+        {
+            let _suppress = suppress::enter();
+            tracing::info!("from the layer");
+        }
+
+        tracing::info!(target: "iroh_like", "kept");
+
+        let records = records(&mut writer)
+            .into_iter()
+            .map(|r| r.message)
+            .collect::<Vec<_>>();
+
+        assert_eq!(records, vec!["kept"]);
+
+        Ok(())
+    }
+
+    // Nothing from the writer's own networking should make it into the
+    // stream, regardless of what the subscriber allows.
+    #[tokio::test]
+    async fn test_writer_leakage() -> Result<()> {
+        let (_subscriber, writer) =
+            subscribe(LayerConfig::default(), LevelFilter::TRACE)?;
+        let mut rx = writer.rx.resubscribe();
+
+        let handle = writer.run().await?;
+        time::sleep(Duration::from_millis(200)).await;
+
+        tracing::info!("user");
+
+        let records = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|r| (r.source.clone(), r.message.clone()))
+            .collect::<Vec<_>>();
+
+        assert!(!handle.is_finished());
+        assert_eq!(
+            records,
+            vec![(
+                Some("laminar_stream::test".to_string()),
+                "user".to_string()
+            )]
+        );
+
+        handle.abort();
+
+        Ok(())
+    }
+
+    // No runtime is needed, and dropping the guard doesn't hang when the
+    // remote can't be reached.
+    #[test]
+    fn test_spawn() -> Result<()> {
+        let (layer, guard) = StreamLayer::builder()
+            .config(layer_config(LayerConfig::default()))
+            .spawn()?;
+
+        assert!(tokio::runtime::Handle::try_current().is_err());
+
+        let _drop_subscriber = tracing_subscriber::registry()
+            .with(LevelFilter::DEBUG)
+            .with(layer)
+            .set_default();
+
+        tracing::info!("before shutdown");
+
+        let start = Instant::now();
+        drop(guard);
+
+        assert!(start.elapsed() < Writer::FLUSH_TIMEOUT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_panics() -> Result<()> {
+        let build = || {
+            StreamLayer::builder()
+                .config(layer_config(
+                    LayerConfig::builder().capture_panics(true).build(),
+                ))
+                .build()
+        };
+        let (layer, mut writer) = build()?;
+
+        let result = std::panic::catch_unwind(|| panic!("boom"));
+        assert!(result.is_err());
+
+        let record = Arc::into_inner(writer.rx.try_recv()?).expect("owned");
+        let fields: serde_json::Value = serde_json::from_str(&record.fields)?;
+
+        assert_eq!(record.message, "boom");
+        assert_eq!(record.level, Some(Level::Error));
+        assert_eq!(fields["panic"]["file"], file!());
+        assert_eq!(fields["panic"]["thread"], "test::test_capture_panics");
+        assert!(fields["panic"]["backtrace"].is_string());
+
+        // The hook stays installed, closing the emitter turns it into a
+        // passthrough.
+        drop(writer);
+        assert!(layer.disabled());
+
+        // Only the latest layer gets panics, and only until it's dropped.
+        let (_first, mut first_writer) = build()?;
+        let (second, mut second_writer) = build()?;
+
+        let result = std::panic::catch_unwind(|| panic!("again"));
+        assert!(result.is_err());
+
+        assert_eq!(second_writer.rx.try_recv()?.message, "again");
+        assert!(second_writer.rx.try_recv().is_err());
+        assert!(first_writer.rx.try_recv().is_err());
+
+        drop(second);
+        let result = std::panic::catch_unwind(|| panic!("dropped"));
+        assert!(result.is_err());
+        assert!(second_writer.rx.try_recv().is_err());
+
+        // When reloading, the hook is there before the remote is, and only
+        // sends once one shows up.
+        let (reloading, mut reloading_writer) = StreamLayer::builder()
+            .config(
+                LayerConfig::builder()
+                    .key(KeySource::Ephemeral)
+                    .capture_panics(true)
+                    .build(),
+            )
+            .reload(true)
+            .build()?;
+
+        let result = std::panic::catch_unwind(|| panic!("no remote"));
+        assert!(result.is_err());
+        assert!(reloading_writer.rx.try_recv().is_err());
+
+        reloading
+            .state
+            .disabled
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        let result = std::panic::catch_unwind(|| panic!("remote"));
+        assert!(result.is_err());
+        assert_eq!(reloading_writer.rx.try_recv()?.message, "remote");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_chain() -> Result<()> {
+        #[derive(Debug, thiserror::Error)]
+        #[error("loading config")]
+        struct ConfigError(#[source] std::io::Error);
+
+        let (_subscriber, mut writer) = subscribe(
+            LayerConfig::builder().error_backtraces(true).build(),
+            LevelFilter::DEBUG,
+        )?;
+
+        let io = std::io::Error::other("missing file");
+        tracing::warn!(error = &io as &dyn std::error::Error, "io");
+
+        let err = ConfigError(io);
+        tracing::error!(error = &err as &dyn std::error::Error, "wrapped");
+
+        let report = eyre::Report::new(std::env::VarError::NotPresent);
+        tracing::warn!(error = &*report as &dyn std::error::Error, "report");
+
+        let records = records(&mut writer)
+            .into_iter()
+            .map(|r| serde_json::from_str(&r.fields))
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+        let [io, wrapped, report] = records.as_slice() else {
+            panic!("expected three records: {records:?}");
+        };
+
+        assert_eq!(io["error"]["message"], "missing file");
+        assert_eq!(io["error"]["type"], "std::io::error::Error");
+        assert_eq!(io["error"]["chain"], serde_json::json!([]));
+        assert!(io["tracing"].get("backtrace").is_none());
+
+        assert_eq!(wrapped["error"]["message"], "loading config");
+        assert!(wrapped["error"].get("type").is_none());
+        assert_eq!(
+            wrapped["error"]["chain"],
+            serde_json::json!(["missing file"])
+        );
+        assert!(wrapped["tracing"]["backtrace"].is_string());
+
+        // Reports are recorded as the error they wrap.
+        assert_eq!(report["error"]["type"], "std::env::VarError");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "valuable")]
+    #[test]
+    fn test_valuable() -> Result<()> {
+        use std::collections::BTreeMap;
+
+        use valuable::{
+            Fields, NamedField, NamedValues, StructDef, Structable, Valuable,
+            Value, Visit,
+        };
+
+        static FIELDS: &[NamedField<'static>] = &[
+            NamedField::new("name"),
+            NamedField::new("roles"),
+            NamedField::new("quota"),
+            NamedField::new("status"),
+        ];
+
+        struct User {
+            name: &'static str,
+            roles: Vec<&'static str>,
+            quota: BTreeMap<&'static str, u32>,
+            status: Result<u32, ()>,
+        }
+
+        impl Valuable for User {
+            fn as_value(&self) -> Value<'_> {
+                Value::Structable(self)
+            }
+
+            fn visit(&self, visit: &mut dyn Visit) {
+                visit.visit_named_fields(&NamedValues::new(
+                    FIELDS,
+                    &[
+                        self.name.as_value(),
+                        self.roles.as_value(),
+                        self.quota.as_value(),
+                        self.status.as_value(),
+                    ],
+                ));
+            }
+        }
+
+        impl Structable for User {
+            fn definition(&self) -> StructDef<'_> {
+                StructDef::new_static("User", Fields::Named(FIELDS))
+            }
+        }
+
+        let (_subscriber, mut writer) =
+            subscribe(LayerConfig::default(), LevelFilter::DEBUG)?;
+
+        let user = User {
+            name: "alice",
+            roles: vec!["admin", "ops"],
+            quota: BTreeMap::from([("disk", 10)]),
+            status: Ok(3),
+        };
+        tracing::info!(user = user.as_value(), "login");
+
+        let record = writer.rx.try_recv().expect("record");
+        let fields: serde_json::Value = serde_json::from_str(&record.fields)?;
+
+        assert_eq!(
+            fields["user"],
+            serde_json::json!({
+                "name": "alice",
+                "roles": ["admin", "ops"],
+                "quota": { "disk": 10 },
+                "status": { "Ok": 3 },
+            })
+        );
+
+        Ok(())
+    }
+
     struct MockDriver;
 
     #[async_trait::async_trait]
     impl SinkDriver for MockDriver {
-        async fn run<T>(self, _: broadcast::Receiver<Arc<T>>)
+        async fn run<T, F>(self, _: broadcast::Receiver<Arc<T>>, _: F)
         where
             T: Serialize + Send + Sync + 'static,
+            F: Fn(Arc<T>) -> Arc<T> + Send + Sync + 'static,
         {
         }
     }
@@ -429,12 +2033,12 @@ mod test {
         let _tel = Telemetry::new();
 
         let (layer, writer) = StreamLayer::builder()
-            .config(LayerConfig::builder().build())
+            .config(LayerConfig::builder().key(KeySource::Ephemeral).build())
             .build()?;
 
         time::timeout(
             Duration::from_millis(10),
-            tokio::spawn(MockDriver {}.run(writer.rx)),
+            tokio::spawn(MockDriver {}.run(writer.rx, |record| record)),
         )
         .await??;
 
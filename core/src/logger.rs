@@ -0,0 +1,108 @@
+// Bridges crates that use `log`, records go to the same emitter as the layer
+// the logger came from, eg `layer.logger().install(LevelFilter::Info)`. Don't
+// combine it with `tracing-log`'s `LogTracer`, records would be sent twice.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::{Record, sink::EmitterSender, suppress};
+
+#[derive(Debug, Clone)]
+pub struct Logger {
+    // Shared with the layer, it follows the config when reloading.
+    pub(crate) disabled: Arc<AtomicBool>,
+    pub(crate) tx: EmitterSender<Record>,
+}
+
+impl Logger {
+    // Sets this as the global logger. `level` is the maximum level that is
+    // forwarded, it applies to every logger.
+    pub fn install(
+        self,
+        level: log::LevelFilter,
+    ) -> Result<(), log::SetLoggerError> {
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(level);
+
+        Ok(())
+    }
+
+    fn disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed) || self.tx.is_closed()
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        !self.disabled() && !suppress::is_suppressed(metadata.target())
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        metrics::counter!("logger.record").increment(1);
+
+        let _suppress = suppress::enter();
+        self.tx.send(Record::from_log(record)).ok();
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use log::Log;
+
+    use super::*;
+    use crate::{Level, sink::emitter};
+
+    #[test]
+    fn test_log() -> Result<(), serde_json::Error> {
+        let (tx, mut rx) = emitter(10);
+        let logger = Logger {
+            disabled: Arc::default(),
+            tx,
+        };
+
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("hello {}", "world"))
+                .level(log::Level::Warn)
+                .target("legacy::db")
+                .module_path_static(Some("legacy::db"))
+                .file_static(Some("src/db.rs"))
+                .line(Some(42))
+                .key_values(&[("rows", 3_i64)])
+                .build(),
+        );
+
+        logger.log(
+            &log::Record::builder()
+                .args(format_args!("transport"))
+                .target("iroh::socket")
+                .build(),
+        );
+
+        let record =
+            Arc::into_inner(rx.try_recv().expect("record")).expect("owned");
+        let fields: serde_json::Value = serde_json::from_str(&record.fields)?;
+
+        assert_eq!(record.message, "hello world");
+        assert_eq!(record.source.as_deref(), Some("legacy::db"));
+        assert_eq!(record.level, Some(Level::Warn));
+        assert_eq!(fields["rows"], 3);
+        assert_eq!(fields["log"]["file"], "src/db.rs");
+        assert_eq!(fields["log"]["line"], 42);
+        assert_eq!(fields["log"]["module_path"], "legacy::db");
+
+        assert!(rx.try_recv().is_err(), "iroh should be suppressed");
+
+        Ok(())
+    }
+}
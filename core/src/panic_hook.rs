@@ -0,0 +1,122 @@
+use std::{
+    panic::{self, PanicHookInfo},
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::watch;
+
+use crate::{
+    Record, client,
+    sink::{EmitterSender, Status, WeakEmitterSender},
+    suppress,
+};
+
+// The process is usually about to exit, this is only long enough for the
+// writer to write what's left in the buffer.
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+static INSTALLED: OnceLock<()> = OnceLock::new();
+// Where panics go, the most recently built layer that captures them.
+static CURRENT: Mutex<Option<Target>> = Mutex::new(None);
+
+struct Target {
+    id: u64,
+    // Weak, so that the hook doesn't keep the writer running.
+    tx: WeakEmitterSender<Record>,
+    status: watch::Receiver<Status>,
+    // The layer's, set while there's no remote.
+    disabled: Arc<AtomicBool>,
+}
+
+// Panics stop going to the layer's writer once this is dropped.
+#[derive(Debug)]
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) struct Registration(u64);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut current = current();
+        if current.as_ref().is_some_and(|target| target.id == self.0) {
+            *current = None;
+        }
+    }
+}
+
+// Sends panics as error records, then chains to whatever hook was installed
+// before, so the usual output is unchanged. The hook itself is only installed
+// once, later layers replace the target instead.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn install(
+    tx: &EmitterSender<Record>,
+    status: watch::Receiver<Status>,
+    disabled: &Arc<AtomicBool>,
+) -> Registration {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    *current() = Some(Target {
+        id,
+        tx: tx.downgrade(),
+        status,
+        disabled: disabled.clone(),
+    });
+
+    INSTALLED.get_or_init(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            report(info);
+            previous(info);
+        }));
+    });
+
+    Registration(id)
+}
+
+fn current() -> MutexGuard<'static, Option<Target>> {
+    CURRENT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn report(info: &PanicHookInfo<'_>) {
+    // A panic on the writer's own thread can't be flushed by the writer.
+    if suppress::is_thread_suppressed() {
+        return;
+    }
+
+    let Some((tx, status)) = current()
+        .as_ref()
+        .filter(|target| !target.disabled.load(Ordering::Relaxed))
+        .and_then(|target| Some((target.tx.upgrade()?, target.status.clone())))
+    else {
+        return;
+    };
+
+    let _suppress = suppress::enter();
+
+    if tx.send(Record::from_panic(info)).is_ok() {
+        flush(status, tx.sent());
+    }
+}
+
+// Waits for the driver to have processed `target` records, the same as
+// `Client::flush`. It's run on a thread of its own, the panicking thread may
+// be inside a runtime already.
+fn flush(status: watch::Receiver<Status>, target: u64) {
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let spawned = std::thread::Builder::new()
+        .name("laminar-panic-flush".into())
+        .spawn(move || {
+            futures::executor::block_on(client::flush(status, target));
+            done_tx.send(()).ok();
+        });
+
+    if spawned.is_ok() {
+        done_rx.recv_timeout(FLUSH_TIMEOUT).ok();
+    }
+}
@@ -0,0 +1,62 @@
+// Carries a `SpanContext` across process boundaries. The sender `inject`s it
+// into its headers, the receiver `extract`s it and passes it to its span as a
+// `traceparent` field, which the layer turns into the span's remote parent.
+
+use std::fmt;
+
+use tracing::field::{Field, Visit};
+use tracing_subscriber::{
+    Registry,
+    registry::{LookupSpan, SpanData},
+};
+
+use crate::api::SpanContext;
+
+pub const TRACEPARENT: &str = "traceparent";
+
+// The context of the current span, if it was created by a `StreamLayer`.
+#[must_use]
+pub fn current() -> Option<SpanContext> {
+    let id = tracing::Span::current().id()?;
+
+    tracing::dispatcher::get_default(|dispatch| {
+        dispatch
+            .downcast_ref::<Registry>()?
+            .span_data(&id)?
+            .extensions()
+            .get::<SpanContext>()
+            .copied()
+    })
+}
+
+// Sets `traceparent` for the current span with the provided setter.
+pub fn inject(mut set: impl FnMut(&str, String)) {
+    if let Some(context) = current() {
+        set(TRACEPARENT, context.to_string());
+    }
+}
+
+// Reads `traceparent` with the provided getter.
+pub fn extract<'a>(
+    get: impl FnOnce(&str) -> Option<&'a str>,
+) -> Option<SpanContext> {
+    get(TRACEPARENT)?.parse().ok()
+}
+
+// Finds a remote parent passed in as a `traceparent` field on a span.
+#[derive(Default)]
+pub(crate) struct RemoteParent(pub(crate) Option<SpanContext>);
+
+impl Visit for RemoteParent {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == TRACEPARENT {
+            self.0 = value.parse().ok();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == TRACEPARENT {
+            self.0 = format!("{value:?}").parse().ok();
+        }
+    }
+}
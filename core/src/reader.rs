@@ -7,16 +7,16 @@ use std::{
 use eyre::Result;
 use futures::Stream;
 use iroh::{
-    Endpoint, PublicKey, SecretKey, address_lookup::MdnsAddressLookup,
-    endpoint::QuicTransportConfig, protocol::Router,
+    Endpoint, PublicKey, SecretKey, endpoint::QuicTransportConfig,
+    protocol::Router,
 };
-use tokio::sync::mpsc::Receiver;
+use tokio::{runtime::Handle, sync::mpsc::Receiver};
 use tracing::Instrument;
 
 use crate::{
     Record,
     api::Claims,
-    config::{Config, ReaderConfig},
+    config::{Config, NetworkConfig, NetworkMode, Reachability, ReaderConfig},
     sink,
 };
 
@@ -45,37 +45,74 @@ impl ReaderBuilder {
             None => Config::load()?.reader(),
         };
 
-        let secret_key = match self.key {
-            Some(key) => key,
-            None => config.key.load_async().await?,
+        let (secret_key, previous) = match self.key {
+            Some(key) => (key, None),
+            None => {
+                (config.key.load_async().await?, config.key.load_previous()?)
+            }
         };
 
-        let transport = QuicTransportConfig::builder()
-            .keep_alive_interval(KEEP_ALIVE_INTERVAL)
-            .build();
-
-        let endpoint = Endpoint::builder()
-            .transport_config(transport)
-            .secret_key(secret_key)
-            .address_lookup(MdnsAddressLookup::builder())
-            .bind()
-            .in_current_span()
-            .await?;
-
-        endpoint.online().await;
-        tracing::info!("endpoint: {}", endpoint.id());
+        let (endpoint, reachability) =
+            bind(secret_key, &config.network).await?;
+        tracing::info!(%reachability, "endpoint: {}", endpoint.id());
 
         let server = sink::Sink::build();
         let (handler, rx) = server.split();
 
+        // After `laminar key rotate`, writers that still have the old address
+        // can keep sending until the grace period is over.
+        let alias = match previous {
+            Some(key) => {
+                let (endpoint, _) = bind(key, &config.network).await?;
+                tracing::info!(
+                    "accepting previous endpoint: {}",
+                    endpoint.id()
+                );
+
+                Some(
+                    Router::builder(endpoint)
+                        .accept(sink::ALPN, handler.clone())
+                        .spawn(),
+                )
+            }
+            None => None,
+        };
+
         let router = Router::builder(endpoint)
             .accept(sink::ALPN, handler)
             .spawn();
 
-        Ok(Reader { rx, router })
+        Ok(Reader {
+            rx,
+            router,
+            alias,
+            mode: config.network.mode,
+            runtime: Handle::try_current().ok(),
+        })
     }
 }
 
+async fn bind(
+    secret_key: SecretKey,
+    network: &NetworkConfig,
+) -> Result<(Endpoint, Reachability)> {
+    let transport = QuicTransportConfig::builder()
+        .keep_alive_interval(KEEP_ALIVE_INTERVAL)
+        .build();
+
+    let endpoint = network
+        .endpoint()
+        .transport_config(transport)
+        .secret_key(secret_key)
+        .bind()
+        .in_current_span()
+        .await?;
+
+    let reachability = network.online(&endpoint).in_current_span().await;
+
+    Ok((endpoint, reachability))
+}
+
 // This is being tested ~implicitly via the test_logging test for the layer.
 // Most of the functionality here is around config management and basic setup.
 // See the sink tests for more in-depth tests for the server side of the sink
@@ -84,6 +121,12 @@ impl ReaderBuilder {
 pub struct Reader {
     rx: Receiver<sink::Response<Claims, Record>>,
     router: Router,
+    // Listens on the key that was rotated out, if it is still valid.
+    alias: Option<Router>,
+    mode: NetworkMode,
+    // The runtime the router was started on. The reader can be dropped
+    // outside of it, eg after `block_on` returns.
+    runtime: Option<Handle>,
 }
 
 impl Reader {
@@ -97,24 +140,40 @@ impl Reader {
         self.router.endpoint().secret_key().public()
     }
 
+    // Changes when the relay connects (or goes away) in the global mode.
+    #[must_use]
+    pub fn reachability(&self) -> Reachability {
+        Reachability::of(self.mode, self.router.endpoint())
+    }
+
     pub async fn shutdown(&self) -> Result<()> {
         self.router.shutdown().await?;
+        if let Some(alias) = &self.alias {
+            alias.shutdown().await?;
+        }
+
         Ok(())
     }
 }
 
 impl Drop for Reader {
     fn drop(&mut self) {
-        let router = self.router.clone();
-        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        let routers = std::iter::once(self.router.clone())
+            .chain(self.alias.clone())
+            .collect::<Vec<_>>();
+        if let Some(handle) =
+            Handle::try_current().ok().or_else(|| self.runtime.clone())
+        {
             handle.spawn(async move {
-                router
-                    .shutdown()
-                    .await
-                    .inspect_err(|e| {
-                        tracing::error!(err = ?e, "failed to shutdown router");
-                    })
-                    .ok();
+                for router in routers {
+                    router
+                        .shutdown()
+                        .await
+                        .inspect_err(|e| {
+                            tracing::error!(err = ?e, "failed to shutdown router");
+                        })
+                        .ok();
+                }
             });
         }
     }
@@ -0,0 +1,280 @@
+// Keeps events more verbose than `level` in memory instead of sending them, and
+// sends them ahead of an event at `trigger` or one with `laminar.trigger =
+// true`. With `per_trace` every trace gets its own window, discarded when its
+// root span closes. See `[layer.recorder]` in the README. Records sent late
+// have `tracing.retroactive` set.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{
+    Event, Metadata,
+    field::{Field, Visit},
+};
+
+use crate::api::{Level, Record};
+
+pub(crate) const TRIGGER_FIELD: &str = "laminar.trigger";
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, bon::Builder)]
+pub struct RecorderConfig {
+    // Events more verbose than this are kept instead of sent. The recorder is
+    // disabled when unset.
+    pub level: Option<Level>,
+    // Number of events kept, per trace with `per_trace`. Defaults to 1000.
+    pub capacity: Option<usize>,
+    // Events at this level or above flush the window. Defaults to error.
+    pub trigger: Option<Level>,
+    #[serde(default)]
+    #[builder(default)]
+    pub per_trace: bool,
+}
+
+// `laminar.trigger = false` is the same as leaving it out.
+#[derive(Default)]
+struct TriggerField(bool);
+
+impl Visit for TriggerField {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == TRIGGER_FIELD {
+            self.0 = value;
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == TRIGGER_FIELD {
+            self.0 = format!("{value:?}") == "true";
+        }
+    }
+}
+
+// Events outside of any span are in the `None` window.
+type Window = Option<u128>;
+
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    level: Level,
+    capacity: usize,
+    trigger: Level,
+    per_trace: bool,
+    windows: Mutex<HashMap<Window, VecDeque<Record>>>,
+}
+
+impl Recorder {
+    const CAPACITY: usize = 1000;
+    // With `per_trace`, a trace that never finishes (eg its root span was
+    // leaked) would keep its window forever. Past this many, the window with
+    // the oldest event is discarded to make room.
+    const MAX_WINDOWS: usize = 1024;
+
+    pub(crate) fn new(config: &RecorderConfig) -> Option<Self> {
+        Some(Self {
+            level: config.level?,
+            capacity: config.capacity.unwrap_or(Self::CAPACITY),
+            trigger: config.trigger.unwrap_or(Level::Error),
+            per_trace: config.per_trace,
+            windows: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Whether the event should be kept instead of sent.
+    pub(crate) fn records(&self, metadata: &Metadata<'_>) -> bool {
+        Level::from(metadata.level()) < self.level
+    }
+
+    pub(crate) fn triggered(&self, event: &Event<'_>) -> bool {
+        let metadata = event.metadata();
+        if Level::from(metadata.level()) >= self.trigger {
+            return true;
+        }
+
+        // Only visited when the field is there, most events don't have it.
+        if metadata.fields().field(TRIGGER_FIELD).is_none() {
+            return false;
+        }
+
+        let mut trigger = TriggerField::default();
+        event.record(&mut trigger);
+        trigger.0
+    }
+
+    pub(crate) fn push(&self, record: Record) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = self.window(&record);
+        let mut windows = self.lock();
+
+        if !windows.contains_key(&key) && windows.len() >= Self::MAX_WINDOWS {
+            Self::evict(&mut windows);
+        }
+
+        let window = windows.entry(key).or_default();
+        if window.len() >= self.capacity {
+            window.pop_front();
+            metrics::counter!("layer.recorder.evicted").increment(1);
+        }
+        window.push_back(record);
+        drop(windows);
+
+        metrics::counter!("layer.recorder.kept").increment(1);
+    }
+
+    // Takes everything that should be sent because of `trigger`, oldest
+    // first.
+    pub(crate) fn flush(&self, trigger: &Record) -> Vec<Record> {
+        let drained = {
+            let mut windows = self.lock();
+
+            if self.per_trace {
+                windows
+                    .remove(&self.window(trigger))
+                    .map(Vec::from)
+                    .unwrap_or_default()
+            } else {
+                windows.drain().flat_map(|(_, window)| window).collect()
+            }
+        };
+
+        metrics::counter!("layer.recorder.flushed")
+            .increment(drained.len() as u64);
+
+        drained
+            .into_iter()
+            .map(|mut record| {
+                record.annotate("retroactive", true.into());
+                record
+            })
+            .collect()
+    }
+
+    // Called when a trace's root span closes, nothing can trigger its window
+    // anymore.
+    pub(crate) fn discard(&self, trace_id: u128) {
+        if self.per_trace {
+            self.lock().remove(&Some(trace_id));
+        }
+    }
+
+    fn window(&self, record: &Record) -> Window {
+        if !self.per_trace {
+            return None;
+        }
+
+        record
+            .trace
+            .as_ref()
+            .and_then(|trace| trace.context)
+            .map(|context| context.trace_id)
+    }
+
+    fn evict(windows: &mut HashMap<Window, VecDeque<Record>>) {
+        let oldest = windows
+            .iter()
+            .min_by_key(|(_, window)| {
+                window.front().map_or(i64::MIN, |record| record.timestamp)
+            })
+            .map(|(key, _)| *key);
+
+        if let Some(key) = oldest {
+            windows.remove(&key);
+        }
+    }
+
+    fn lock(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<Window, VecDeque<Record>>> {
+        self.windows.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{SpanContext, TraceId};
+
+    fn record(message: &str, trace_id: Option<u128>) -> Record {
+        Record::builder()
+            .message(message.to_string())
+            .maybe_trace(trace_id.map(|trace_id| TraceId {
+                span: None,
+                parent: None,
+                context: Some(SpanContext {
+                    trace_id,
+                    span_id: 1,
+                }),
+                parent_context: None,
+                follows_from: Vec::new(),
+            }))
+            .fields("{}")
+            .build()
+    }
+
+    fn messages(records: &[Record]) -> Vec<&str> {
+        records.iter().map(|r| r.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_ring() -> Result<(), serde_json::Error> {
+        let recorder = Recorder::new(
+            &RecorderConfig::builder()
+                .level(Level::Info)
+                .capacity(2)
+                .build(),
+        )
+        .expect("enabled");
+
+        for message in ["a", "b", "c"] {
+            recorder.push(record(message, None));
+        }
+
+        let flushed = recorder.flush(&record("boom", None));
+        assert_eq!(messages(&flushed), vec!["b", "c"]);
+
+        let fields: serde_json::Value =
+            serde_json::from_str(&flushed[0].fields)?;
+        assert_eq!(fields["tracing"]["retroactive"], true);
+
+        assert!(recorder.flush(&record("boom", None)).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_trace() {
+        let recorder = Recorder::new(
+            &RecorderConfig::builder()
+                .level(Level::Info)
+                .per_trace(true)
+                .build(),
+        )
+        .expect("enabled");
+
+        recorder.push(record("a1", Some(1)));
+        recorder.push(record("b1", Some(2)));
+        recorder.push(record("a2", Some(1)));
+        recorder.push(record("c1", Some(3)));
+
+        assert_eq!(
+            messages(&recorder.flush(&record("boom", Some(1)))),
+            vec!["a1", "a2"]
+        );
+
+        recorder.discard(3);
+        assert!(recorder.flush(&record("boom", Some(3))).is_empty());
+        assert_eq!(
+            messages(&recorder.flush(&record("boom", Some(2)))),
+            vec!["b1"]
+        );
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert!(Recorder::new(&RecorderConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,262 @@
+// Masks secrets in every record the writer sends, from the layer or `laminar
+// tap`, the sink stores whatever it gets. See `[layer.redact]` in the README.
+// Records have `tracing.redacted` set to the number of replacements, 0 when
+// nothing matched.
+
+use std::sync::Arc;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::api::Record;
+
+const MASK: &str = "[REDACTED]";
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, bon::Builder)]
+pub struct RedactConfig {
+    // Field names, compared case-insensitively, whose values are replaced
+    // entirely.
+    #[serde(default)]
+    #[builder(default)]
+    pub fields: Vec<String>,
+    // Regular expressions, see the `regex` crate for the syntax.
+    #[serde(default)]
+    #[builder(default)]
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    #[builder(default)]
+    pub detectors: Vec<Detector>,
+}
+
+// Built-in patterns for common secrets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Detector {
+    Email,
+    Jwt,
+    // `Authorization: Bearer ...` style tokens, including the scheme.
+    Bearer,
+    AwsAccessKey,
+    GithubToken,
+    SlackToken,
+    PrivateKey,
+}
+
+impl Detector {
+    const fn pattern(self) -> &'static str {
+        match self {
+            Self::Email => r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+            Self::Jwt => {
+                r"eyJ[A-Za-z0-9_-]+\.eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+"
+            }
+            Self::Bearer => r"(?i)\bbearer\s+[A-Za-z0-9\-._~+/]+=*",
+            Self::AwsAccessKey => r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+            Self::GithubToken => {
+                r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})"
+            }
+            Self::SlackToken => r"\bxox[abprs]-[A-Za-z0-9-]{10,}",
+            Self::PrivateKey => {
+                r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----"
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Redactor {
+    fields: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    // `None` when there is nothing to redact, records are passed through
+    // without being parsed.
+    pub(crate) fn new(
+        config: &RedactConfig,
+    ) -> Result<Option<Self>, regex::Error> {
+        let patterns = config
+            .detectors
+            .iter()
+            .map(|detector| detector.pattern())
+            .chain(config.patterns.iter().map(String::as_str))
+            .map(Regex::new)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if config.fields.is_empty() && patterns.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            fields: config.fields.iter().map(|f| f.to_lowercase()).collect(),
+            patterns,
+        }))
+    }
+
+    // The fields are parsed once, masked and annotated with the count, then
+    // written back.
+    pub(crate) fn redact(&self, record: Arc<Record>) -> Arc<Record> {
+        let mut record = Arc::unwrap_or_clone(record);
+
+        let (message, mut count) = self.mask(&record.message);
+        record.message = message;
+
+        if let Ok(mut fields) = serde_json::from_str(&record.fields) {
+            count += self.walk(&mut fields);
+
+            if let serde_json::Value::Object(fields) = &mut fields {
+                Record::annotate_fields(fields, "redacted", count.into());
+            }
+            record.fields = fields.to_string();
+        }
+
+        metrics::counter!("writer.redacted").increment(count);
+
+        Arc::new(record)
+    }
+
+    fn walk(&self, value: &mut serde_json::Value) -> u64 {
+        match value {
+            serde_json::Value::String(s) => {
+                let (masked, count) = self.mask(s);
+                *s = masked;
+                count
+            }
+            serde_json::Value::Array(values) => {
+                values.iter_mut().map(|v| self.walk(v)).sum()
+            }
+            serde_json::Value::Object(fields) => fields
+                .iter_mut()
+                .map(|(key, v)| {
+                    if self.fields.contains(&key.to_lowercase()) {
+                        *v = MASK.into();
+                        1
+                    } else {
+                        self.walk(v)
+                    }
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+
+    fn mask(&self, value: &str) -> (String, u64) {
+        let mut count = 0;
+        let mut value = value.to_string();
+
+        for pattern in &self.patterns {
+            let found = pattern.find_iter(&value).count() as u64;
+            if found > 0 {
+                value = pattern.replace_all(&value, MASK).into_owned();
+                count += found;
+            }
+        }
+
+        (value, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(config: &RedactConfig) -> Redactor {
+        Redactor::new(config).expect("valid").expect("enabled")
+    }
+
+    #[test]
+    fn test_fields() -> Result<(), serde_json::Error> {
+        let redactor = redactor(
+            &RedactConfig::builder()
+                .fields(vec!["Password".into()])
+                .build(),
+        );
+
+        let record = redactor.redact(Arc::new(
+            Record::builder()
+                .message("login".into())
+                .fields(
+                    serde_json::json!({
+                        "user": "alice",
+                        "password": "hunter2",
+                        "nested": { "PASSWORD": { "old": "hunter1" } },
+                        "tracing": {},
+                    })
+                    .to_string(),
+                )
+                .build(),
+        ));
+
+        let fields: serde_json::Value = serde_json::from_str(&record.fields)?;
+        assert_eq!(fields["user"], "alice");
+        assert_eq!(fields["password"], MASK);
+        assert_eq!(fields["nested"]["PASSWORD"], MASK);
+        assert_eq!(fields["tracing"]["redacted"], 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_patterns() -> Result<(), serde_json::Error> {
+        let redactor = redactor(
+            &RedactConfig::builder()
+                .patterns(vec![r"customer-\d+".into()])
+                .detectors(vec![Detector::Email, Detector::Bearer])
+                .build(),
+        );
+
+        let record = redactor.redact(Arc::new(
+            Record::builder()
+                .message("sent to alice@example.com for customer-42".into())
+                .fields(
+                    serde_json::json!({
+                        "headers": ["Authorization: Bearer abc.def-123"],
+                    })
+                    .to_string(),
+                )
+                .build(),
+        ));
+
+        let fields: serde_json::Value = serde_json::from_str(&record.fields)?;
+        assert_eq!(record.message, "sent to [REDACTED] for [REDACTED]");
+        assert_eq!(fields["headers"][0], "Authorization: [REDACTED]");
+        // Tapped lines don't have any metadata of their own yet.
+        assert_eq!(fields["tracing"]["redacted"], 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unchanged() -> Result<(), serde_json::Error> {
+        let redactor = redactor(
+            &RedactConfig::builder()
+                .detectors(vec![Detector::Jwt])
+                .build(),
+        );
+
+        let record = redactor.redact(Arc::new(
+            Record::builder().message("hi".into()).fields("{}").build(),
+        ));
+
+        // Checked, with nothing to mask.
+        let fields: serde_json::Value = serde_json::from_str(&record.fields)?;
+        assert_eq!(record.message, "hi");
+        assert_eq!(fields["tracing"]["redacted"], 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert!(
+            Redactor::new(&RedactConfig::default())
+                .expect("valid")
+                .is_none()
+        );
+        assert!(
+            Redactor::new(
+                &RedactConfig::builder().patterns(vec!["(".into()]).build()
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,292 @@
+// Applies changes to the config file while a writer is running. The file is
+// polled rather than watched, that works the same everywhere (including
+// network filesystems) and a couple of seconds don't matter for config.
+//
+// Not everything can change live:
+//
+// - `remote`, `display_name`, `labels` and `detect_labels` move the driver
+//   over, see `Driver::follow`. Anything still buffered is sent to the new
+//   remote.
+// - `filter` is swapped in place.
+// - `capture_panics` and `capture_output` follow `remote`. With reload on, they
+//   are set up when the layer is built even without a remote, and only send
+//   while there is one. Turning them on or off needs a restart.
+// - The rest (eg `key`, `network`, `redact`) is only read when the layer is
+//   built and needs a restart.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, PoisonError, RwLock, RwLockReadGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use eyre::Result;
+use tokio::{sync::watch, time};
+use tracing_subscriber::EnvFilter;
+
+use crate::{Config, config::LayerConfig};
+
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) const INTERVAL: Duration = Duration::from_secs(2);
+
+// The parts of `StreamLayer` that follow the config, shared with the writer
+// that applies the changes.
+#[derive(Debug)]
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) struct LayerState {
+    // Shared with `Logger` as well.
+    pub(crate) disabled: Arc<AtomicBool>,
+    filter: RwLock<Option<EnvFilter>>,
+}
+
+fn parse_filter(config: &LayerConfig) -> Result<Option<EnvFilter>> {
+    Ok(config
+        .filter
+        .as_deref()
+        .map(EnvFilter::try_new)
+        .transpose()?)
+}
+
+impl LayerState {
+    pub(crate) fn new(config: &LayerConfig) -> Result<Self> {
+        Ok(Self {
+            disabled: Arc::new(AtomicBool::new(config.remote.is_none())),
+            filter: RwLock::new(parse_filter(config)?),
+        })
+    }
+
+    pub(crate) fn disabled(&self) -> bool {
+        self.disabled.load(Ordering::Relaxed)
+    }
+
+    // Don't hold on to this, `apply` waits for it.
+    pub(crate) fn filter(&self) -> RwLockReadGuard<'_, Option<EnvFilter>> {
+        self.filter.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // An invalid filter keeps the previous one, everything else still
+    // applies.
+    pub(crate) fn apply(&self, config: &LayerConfig) {
+        self.disabled
+            .store(config.remote.is_none(), Ordering::Relaxed);
+
+        match parse_filter(config) {
+            Ok(filter) => {
+                *self.filter.write().unwrap_or_else(PoisonError::into_inner) =
+                    filter;
+            }
+            Err(e) => {
+                tracing::warn!(err = ?e, "invalid filter, keeping the old one");
+            }
+        }
+    }
+}
+
+// Reloads the config from `path` whenever it changes. Without a path,
+// `LAMINAR_CONFIG` is looked up each time, pointing it at another file counts
+// as a change too. Polling stops once every receiver is gone.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn watch(
+    path: Option<PathBuf>,
+    initial: LayerConfig,
+    interval: Duration,
+) -> watch::Receiver<LayerConfig> {
+    let (tx, rx) = watch::channel(initial);
+    tokio::spawn(poll(path, tx, interval));
+
+    rx
+}
+
+// Changes to a file that's being written might be seen before the write is
+// done, the length catches most of those.
+type Stamp = (PathBuf, Option<(SystemTime, u64)>);
+
+fn stamp(path: &Path) -> Stamp {
+    let modified = std::fs::metadata(path)
+        .and_then(|meta| Ok((meta.modified()?, meta.len())))
+        .ok();
+
+    (path.to_path_buf(), modified)
+}
+
+async fn poll(
+    path: Option<PathBuf>,
+    tx: watch::Sender<LayerConfig>,
+    interval: Duration,
+) {
+    let mut interval = time::interval(interval);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+    // The initial config was loaded right before this started.
+    let mut last = None;
+
+    loop {
+        tokio::select! {
+            () = tx.closed() => break,
+            _ = interval.tick() => {}
+        }
+
+        let file = path.clone().unwrap_or_else(|| Config::resolve_path(None));
+        let current = stamp(&file);
+        if last.as_ref() == Some(&current) {
+            continue;
+        }
+
+        let first = last.is_none();
+        last = Some(current);
+        if first {
+            continue;
+        }
+
+        let layer = match Config::load_with(Some(&file.to_string_lossy()), None)
+        {
+            Ok(config) => config.layer(),
+            Err(e) => {
+                tracing::warn!(
+                    path = %file.display(), err = %e,
+                    "unable to reload config"
+                );
+                continue;
+            }
+        };
+
+        let changed = tx.send_if_modified(|current| {
+            if same(current, &layer) {
+                return false;
+            }
+
+            *current = layer;
+            true
+        });
+
+        if changed {
+            metrics::counter!("layer.reload").increment(1);
+            tracing::info!(path = %file.display(), "config reloaded");
+        }
+    }
+}
+
+// `LayerConfig` holds a few types that can't be compared directly. A remote
+// from the address book is serialized as just its name, so it is compared on
+// its own, editing the peer's entry is a change too.
+fn same(a: &LayerConfig, b: &LayerConfig) -> bool {
+    if a.remote != b.remote {
+        return false;
+    }
+
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watch() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-reload-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[layer]\nfilter = \"info\"\n")?;
+
+        let initial = Config::load_from_path(path.to_string_lossy())?.layer();
+        let mut rx =
+            watch(Some(path.clone()), initial, Duration::from_millis(10));
+        time::sleep(Duration::from_millis(50)).await;
+
+        let key = SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public();
+        std::fs::write(
+            &path,
+            format!("[layer]\nfilter = \"debug\"\nremote = \"{key}\"\n"),
+        )?;
+
+        time::timeout(Duration::from_secs(1), rx.changed()).await??;
+        let layer = rx.borrow_and_update().clone();
+        assert_eq!(layer.filter.as_deref(), Some("debug"));
+        assert_eq!(layer.remote.and_then(|remote| remote.key()), Some(key));
+
+        // A broken file is skipped, the last good config stays.
+        std::fs::write(&path, "[layer\n")?;
+        assert!(
+            time::timeout(Duration::from_millis(100), rx.changed())
+                .await
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    // Moving a peer in the address book moves a remote that refers to it.
+    #[tokio::test]
+    async fn test_watch_peer() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("laminar-reload-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("config.toml");
+
+        let key = SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public();
+        let config = |addr: &str| {
+            format!(
+                "[layer]\nremote = \"sink\"\n\n[peers.sink]\nkey = \
+                 \"{key}\"\naddrs = [\"{addr}\"]\n"
+            )
+        };
+        std::fs::write(&path, config("127.0.0.1:4000"))?;
+
+        let initial = Config::load_from_path(path.to_string_lossy())?.layer();
+        let mut rx =
+            watch(Some(path.clone()), initial, Duration::from_millis(10));
+        time::sleep(Duration::from_millis(50)).await;
+
+        std::fs::write(&path, config("127.0.0.1:4001"))?;
+
+        time::timeout(Duration::from_secs(1), rx.changed()).await??;
+        let addr = rx
+            .borrow_and_update()
+            .remote
+            .as_ref()
+            .and_then(crate::config::Remote::addr)
+            .expect("a remote");
+        assert_eq!(
+            addr.ip_addrs().copied().collect::<Vec<_>>(),
+            vec!["127.0.0.1:4001".parse()?]
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply() -> Result<()> {
+        let state = LayerState::new(&LayerConfig::default())?;
+        assert!(state.disabled());
+        assert!(state.filter().is_none());
+
+        let key = SecretKey::from_bytes(&rand::random::<[u8; 32]>()).public();
+        state
+            .apply(&LayerConfig::builder().remote(key).filter("debug").build());
+        assert!(!state.disabled());
+        assert!(state.filter().is_some());
+
+        state.apply(
+            &LayerConfig::builder()
+                .remote(key)
+                .filter("not a [valid filter")
+                .build(),
+        );
+        assert!(state.filter().is_some(), "the old filter is kept");
+
+        Ok(())
+    }
+}
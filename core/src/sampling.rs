@@ -0,0 +1,264 @@
+// Keeps noisy callsites, eg a `debug!` in a hot loop, from lagging the writer.
+// Every policy applies per callsite, see `[layer.sampling]` in the README. The
+// next event that gets through has `tracing.suppressed` set to how many were
+// dropped before it. Spans are never sampled, that would orphan their events.
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{Metadata, callsite::Identifier};
+
+use crate::api::Level;
+
+#[derive(Debug, Clone, Deserialize, Serialize, bon::Builder)]
+pub struct SamplingConfig {
+    // Maximum number of events per second from a single callsite.
+    pub rate_limit: Option<u32>,
+    // Always keep the first N events from a callsite. When `every` is unset,
+    // everything after that is dropped.
+    pub first: Option<u64>,
+    // After `first`, keep every Mth event from a callsite.
+    pub every: Option<u64>,
+    // Probabilistic sampling, the first matching rule wins.
+    #[serde(default)]
+    #[builder(default)]
+    pub rules: Vec<SampleRule>,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl SamplingConfig {
+    const fn is_enabled(&self) -> bool {
+        self.rate_limit.is_some()
+            || self.first.is_some()
+            || self.every.is_some()
+            || !self.rules.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, bon::Builder)]
+pub struct SampleRule {
+    // Matches the target and any of its children, `hyper` matches
+    // `hyper::proto` but not `hyper_util`.
+    #[builder(into)]
+    pub target: Option<String>,
+    // Matches this level and anything more verbose.
+    pub level: Option<Level>,
+    // Fraction of matching events to keep, from 0 to 1.
+    pub rate: f64,
+}
+
+impl SampleRule {
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        let target = self.target.as_deref().is_none_or(|prefix| {
+            metadata
+                .target()
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        });
+        let level = self
+            .level
+            .is_none_or(|level| Level::from(metadata.level()) <= level);
+
+        target && level
+    }
+}
+
+#[derive(Debug)]
+struct Callsite {
+    seen: u64,
+    window: Instant,
+    in_window: u32,
+    suppressed: u64,
+}
+
+impl Callsite {
+    fn new() -> Self {
+        Self {
+            seen: 0,
+            window: Instant::now(),
+            in_window: 0,
+            suppressed: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Sampler {
+    config: SamplingConfig,
+    // Lock-free lookup, the lock is only shared with events from the same
+    // callsite.
+    callsites: papaya::HashMap<Identifier, Mutex<Callsite>>,
+}
+
+impl Sampler {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    // There's no point keeping track of every callsite when nothing has been
+    // configured.
+    pub(crate) fn new(config: SamplingConfig) -> Option<Self> {
+        config.is_enabled().then(|| Self {
+            config,
+            callsites: papaya::HashMap::new(),
+        })
+    }
+
+    // Returns `None` when the event should be dropped. Otherwise, it is the
+    // number of events from the same callsite that were dropped since the
+    // last one was kept.
+    pub(crate) fn sample(&self, metadata: &Metadata<'_>) -> Option<u64> {
+        let keep_rule = self
+            .config
+            .rules
+            .iter()
+            .find(|rule| rule.matches(metadata))
+            .is_none_or(|rule| rand::random::<f64>() < rule.rate);
+
+        let callsites = self.callsites.pin();
+        let kept = self.decide(
+            &mut callsites
+                .get_or_insert_with(metadata.callsite(), || {
+                    Mutex::new(Callsite::new())
+                })
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            keep_rule,
+        );
+
+        if kept.is_none() {
+            metrics::counter!("layer.sampled.event").increment(1);
+        }
+
+        kept
+    }
+
+    fn decide(&self, state: &mut Callsite, keep_rule: bool) -> Option<u64> {
+        state.seen += 1;
+
+        if keep_rule && self.keep_nth(state.seen) && self.keep_rate(state) {
+            Some(std::mem::take(&mut state.suppressed))
+        } else {
+            state.suppressed += 1;
+            None
+        }
+    }
+
+    fn keep_nth(&self, seen: u64) -> bool {
+        let first = self.config.first.unwrap_or(0);
+
+        if seen <= first {
+            return true;
+        }
+
+        self.config.every.map_or_else(
+            || self.config.first.is_none(),
+            |every| (seen - first).is_multiple_of(every.max(1)),
+        )
+    }
+
+    fn keep_rate(&self, state: &mut Callsite) -> bool {
+        let Some(limit) = self.config.rate_limit else {
+            return true;
+        };
+
+        let now = Instant::now();
+        if now.saturating_duration_since(state.window) >= Self::WINDOW {
+            state.window = now;
+            state.in_window = 0;
+        }
+
+        if state.in_window >= limit {
+            return false;
+        }
+
+        state.in_window += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{
+        Level as TracingLevel, callsite::Callsite as _, metadata::Kind,
+    };
+
+    use super::*;
+
+    // The metadata of a real callsite, each use is a different one.
+    macro_rules! metadata {
+        ($target:literal, $level:expr) => {
+            tracing::callsite! {
+                name: "test",
+                kind: Kind::EVENT,
+                target: $target,
+                level: $level,
+                fields: []
+            }
+            .metadata()
+        };
+    }
+
+    fn run(sampler: &Sampler, meta: &Metadata<'_>, n: usize) -> Vec<u64> {
+        (0..n).filter_map(|_| sampler.sample(meta)).collect()
+    }
+
+    #[test]
+    fn test_first_then_every() {
+        let site = metadata!("app", TracingLevel::DEBUG);
+        let sampler =
+            Sampler::new(SamplingConfig::builder().first(2).every(3).build())
+                .expect("enabled");
+
+        // 1, 2 are kept, then 5, 8 and so on.
+        assert_eq!(run(&sampler, site, 8), vec![0, 0, 2, 2]);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let a = metadata!("app", TracingLevel::INFO);
+        let b = metadata!("app", TracingLevel::INFO);
+        let sampler =
+            Sampler::new(SamplingConfig::builder().rate_limit(3).build())
+                .expect("enabled");
+
+        assert_eq!(run(&sampler, a, 10).len(), 3);
+        // Limits are per callsite.
+        assert_eq!(run(&sampler, b, 10).len(), 3);
+    }
+
+    #[test]
+    fn test_rules() {
+        let noisy = metadata!("hyper::proto", TracingLevel::TRACE);
+        let warn = metadata!("hyper::proto", TracingLevel::WARN);
+        let other = metadata!("hyper_util", TracingLevel::TRACE);
+
+        let sampler = Sampler::new(
+            SamplingConfig::builder()
+                .rules(vec![
+                    SampleRule::builder()
+                        .target("hyper")
+                        .level(Level::Debug)
+                        .rate(0.0)
+                        .build(),
+                ])
+                .build(),
+        )
+        .expect("enabled");
+
+        assert!(run(&sampler, noisy, 10).is_empty());
+        assert_eq!(run(&sampler, warn, 10).len(), 10);
+        assert_eq!(run(&sampler, other, 10).len(), 10);
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert!(Sampler::new(SamplingConfig::default()).is_none());
+    }
+}
@@ -3,7 +3,10 @@ mod session;
 
 use std::{
     io::{Error as IoError, ErrorKind},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
@@ -14,7 +17,7 @@ use iroh::{
     endpoint::{Connection, RecvStream},
     protocol::{AcceptError, ProtocolHandler},
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use strum::EnumDiscriminants;
 use tokio::{
@@ -24,9 +27,9 @@ use tokio::{
 use tracing::Instrument;
 use uuid::Uuid;
 
-use crate::api;
+use crate::api::{self, ResourceSample};
 
-pub const ALPN: &[u8] = b"laminar/sink/0";
+pub const ALPN: &[u8] = b"laminar/sink/1";
 
 pub(crate) type BoxError = Box<dyn std::error::Error + Send + Sync>;
 pub(crate) use driver::Driver;
@@ -71,6 +74,24 @@ pub enum ResponseEvent<T> {
     Error(String),
     Disconnect(DisconnectReason),
     Data(T),
+    Resources(ResourceSample),
+}
+
+// Everything after the handshake is wrapped in a frame so that writers can
+// send more than just records over the same stream.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Frame<T> {
+    Data(T),
+    Resources(ResourceSample),
+}
+
+impl<T> From<Frame<T>> for ResponseEvent<T> {
+    fn from(frame: Frame<T>) -> Self {
+        match frame {
+            Frame::Data(data) => Self::Data(data),
+            Frame::Resources(sample) => Self::Resources(sample),
+        }
+    }
 }
 
 impl<T> ResponseEvent<T> {
@@ -176,6 +197,15 @@ pub struct SinkHandler<Assertion, Body> {
     emit: mpsc::Sender<Response<Assertion, Body>>,
 }
 
+// A derive would require `Assertion` and `Body` to be `Clone` as well.
+impl<Assertion, Body> Clone for SinkHandler<Assertion, Body> {
+    fn clone(&self) -> Self {
+        Self {
+            emit: self.emit.clone(),
+        }
+    }
+}
+
 impl<Assertion, Body> ProtocolHandler for SinkHandler<Assertion, Body>
 where
     Assertion: serde::de::DeserializeOwned
@@ -241,7 +271,7 @@ where
             };
 
             let msg_stream = stream::try_unfold(byte_stream, |byte_stream| {
-                get_frame(byte_stream).in_current_span()
+                get_frame::<Frame<Body>>(byte_stream).in_current_span()
             })
             .boxed();
 
@@ -263,9 +293,12 @@ where
 
 #[async_trait::async_trait]
 pub(crate) trait SinkDriver {
-    async fn run<T>(self, rx: broadcast::Receiver<Arc<T>>)
+    // `prepare` is called on everything that is received, right before it is
+    // serialized.
+    async fn run<T, F>(self, rx: broadcast::Receiver<Arc<T>>, prepare: F)
     where
-        T: Serialize + Send + Sync + 'static;
+        T: Serialize + Send + Sync + 'static,
+        F: Fn(Arc<T>) -> Arc<T> + Send + Sync + 'static;
 }
 
 #[must_use]
@@ -273,16 +306,36 @@ pub fn emitter<T>(
     buffer_size: usize,
 ) -> (EmitterSender<T>, broadcast::Receiver<Arc<T>>) {
     let (tx, rx) = broadcast::channel(buffer_size);
-    (EmitterSender(tx), rx)
+    (
+        EmitterSender {
+            tx,
+            sent: Arc::default(),
+        },
+        rx,
+    )
 }
 
 #[derive(Debug)]
-pub struct EmitterSender<T>(broadcast::Sender<Arc<T>>);
+pub struct EmitterSender<T> {
+    tx: broadcast::Sender<Arc<T>>,
+    // Shared by all the clones, see `sent`.
+    sent: Arc<AtomicU64>,
+}
+
+// Derive would require `T: Clone`.
+impl<T> Clone for EmitterSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            sent: self.sent.clone(),
+        }
+    }
+}
 
 impl<T> EmitterSender<T> {
     #[must_use]
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.tx.len()
     }
 
     #[must_use]
@@ -292,14 +345,78 @@ impl<T> EmitterSender<T> {
 
     #[must_use]
     pub fn is_closed(&self) -> bool {
-        self.0.receiver_count() == 0
+        self.tx.receiver_count() == 0
+    }
+
+    // Everything sent so far from any of the clones, the driver has caught up
+    // once `Status::processed` gets here.
+    #[must_use]
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    // Doesn't keep the channel open.
+    #[must_use]
+    pub fn downgrade(&self) -> WeakEmitterSender<T> {
+        WeakEmitterSender {
+            tx: self.tx.downgrade(),
+            sent: self.sent.clone(),
+        }
     }
 
     pub fn send(
         &self,
         message: T,
     ) -> Result<usize, broadcast::error::SendError<Arc<T>>> {
-        self.0.send(Arc::new(message))
+        let receivers = self.tx.send(Arc::new(message))?;
+        self.sent.fetch_add(1, Ordering::Relaxed);
+
+        Ok(receivers)
+    }
+}
+
+#[derive(Debug)]
+pub struct WeakEmitterSender<T> {
+    tx: broadcast::WeakSender<Arc<T>>,
+    sent: Arc<AtomicU64>,
+}
+
+impl<T> WeakEmitterSender<T> {
+    // `None` once every `EmitterSender` has been dropped.
+    #[must_use]
+    pub fn upgrade(&self) -> Option<EmitterSender<T>> {
+        Some(EmitterSender {
+            tx: self.tx.upgrade()?,
+            sent: self.sent.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    // Not connected yet, or reconnecting after the connection was lost.
+    Connecting,
+    Connected,
+    // The driver has exited, nothing else will be sent.
+    Stopped,
+}
+
+// Reported by the driver as it goes, see `Client::status`.
+#[derive(Debug, Clone, Copy)]
+pub struct Status {
+    pub state: ConnectionState,
+    // Everything taken off the emitter so far. This includes records that
+    // failed to send or were skipped because the driver lagged, they aren't
+    // coming back either way.
+    pub processed: u64,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self {
+            state: ConnectionState::Connecting,
+            processed: 0,
+        }
     }
 }
 
@@ -311,6 +428,7 @@ pub struct EmitterOpts {
     pub connect_timeout: Duration,
     #[builder(default = Duration::from_secs(10))]
     pub retry_interval: Duration,
+    pub resource_interval: Option<Duration>,
 }
 
 impl Default for EmitterOpts {
@@ -330,6 +448,14 @@ where
     identity: Assertion,
     #[builder(default)]
     opts: EmitterOpts,
+    // Process to send resource samples for, see
+    // `EmitterOpts::resource_interval`.
+    sample_pid: Option<u32>,
+    // Flushes anything that is buffered and stops the driver when fired or
+    // dropped. Without it, the driver runs until the emitter is closed.
+    shutdown: Option<tokio::sync::oneshot::Receiver<()>>,
+    // Updated with the connection state and progress of the driver.
+    status: Option<tokio::sync::watch::Sender<Status>>,
 }
 
 impl<Assertion> Client<Assertion>
@@ -342,6 +468,9 @@ where
             .addr(self.address)
             .identity(postcard::to_allocvec(&self.identity).unwrap())
             .opts(self.opts)
+            .maybe_sample_pid(self.sample_pid)
+            .maybe_shutdown(self.shutdown)
+            .maybe_status(self.status)
             .build()
     }
 }
@@ -353,7 +482,7 @@ mod tests {
         SecretKey, address_lookup::MdnsAddressLookup, protocol::Router,
     };
     use laminar_testing::Telemetry;
-    use tokio::time;
+    use tokio::{sync::watch, time};
 
     use super::*;
     use crate::now;
@@ -378,6 +507,8 @@ mod tests {
         Ok((rx, router))
     }
 
+    // Predates `manual_assert_eq`.
+    #[allow(clippy::manual_assert_eq)]
     #[tokio::test]
     async fn test_client() -> Result<()> {
         const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
@@ -408,7 +539,7 @@ mod tests {
             .into_driver();
 
         let (emitter, rx) = emitter(opts.buffer_size);
-        tokio::spawn(driver.run(rx));
+        tokio::spawn(driver.run(rx, |data| data));
 
         // Initial connection, this technically tests reconnect as the router
         // isn't running when the emitter starts up.
@@ -473,4 +604,151 @@ mod tests {
 
         Ok(())
     }
+
+    // Reachable over loopback, without waiting for discovery or relays.
+    async fn local_server(
+        key: SecretKey,
+    ) -> Result<(mpsc::Receiver<Response<(), u16>>, Router, EndpointAddr)> {
+        let endpoint = Endpoint::builder().secret_key(key).bind().await?;
+
+        let addr = endpoint
+            .bound_sockets()
+            .into_iter()
+            .filter(std::net::SocketAddr::is_ipv4)
+            .fold(EndpointAddr::new(endpoint.id()), |addr, socket| {
+                addr.with_ip_addr(
+                    (std::net::Ipv4Addr::LOCALHOST, socket.port()).into(),
+                )
+            });
+
+        let (handler, rx) = Sink::<(), u16>::build().split();
+        let router = Router::builder(endpoint).accept(ALPN, handler).spawn();
+
+        Ok((rx, router, addr))
+    }
+
+    // Records buffered while moving to another sink aren't lost, and the new
+    // sink gets the new identity.
+    #[tokio::test]
+    async fn test_retarget() -> Result<()> {
+        const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+        // Heartbeats can show up anywhere.
+        async fn next(
+            rx: &mut mpsc::Receiver<Response<(), u16>>,
+        ) -> ResponseEvent<u16> {
+            loop {
+                let resp = rx.recv().await.expect("to be open");
+                if !matches!(resp.event, ResponseEvent::Heartbeat) {
+                    return resp.event;
+                }
+            }
+        }
+
+        let _ctx = Telemetry::new();
+
+        let (mut first, _first_router, first_addr) =
+            local_server(SecretKey::from_bytes(&rand::random::<[u8; 32]>()))
+                .await?;
+        let (mut second, _second_router, second_addr) =
+            local_server(SecretKey::from_bytes(&rand::random::<[u8; 32]>()))
+                .await?;
+
+        let endpoint = Endpoint::builder()
+            .secret_key(SecretKey::from_bytes(&rand::random::<[u8; 32]>()))
+            .bind()
+            .await?;
+
+        let (targets, updates) = watch::channel(None);
+        let driver = Client::builder()
+            .endpoint(endpoint)
+            .address(first_addr)
+            .identity(())
+            .opts(
+                EmitterOpts::builder()
+                    .retry_interval(Duration::from_millis(100))
+                    .build(),
+            )
+            .build()
+            .into_driver()
+            .follow(updates);
+
+        let (emitter, rx) = emitter::<u16>(10);
+        tokio::spawn(driver.run(rx, |data| data));
+
+        emitter.send(0)?;
+        time::timeout(RECEIVE_TIMEOUT, async {
+            assert!(matches!(next(&mut first).await, ResponseEvent::Connect));
+            assert!(matches!(next(&mut first).await, ResponseEvent::Data(0)));
+        })
+        .await?;
+
+        targets.send(Some(driver::Target::new(second_addr, &())?))?;
+
+        // Sent while the driver is connecting to the second sink.
+        time::timeout(RECEIVE_TIMEOUT, async {
+            assert!(matches!(
+                next(&mut first).await,
+                ResponseEvent::Disconnect(_)
+            ));
+        })
+        .await?;
+        emitter.send(1)?;
+
+        time::timeout(RECEIVE_TIMEOUT, async {
+            assert!(matches!(next(&mut second).await, ResponseEvent::Connect));
+            assert!(matches!(next(&mut second).await, ResponseEvent::Data(1)));
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    // Samples of the source process are sent alongside the data, as their own
+    // events.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_resources() -> Result<()> {
+        let _ctx = Telemetry::new();
+
+        let (mut rx, _router, addr) =
+            local_server(SecretKey::from_bytes(&rand::random::<[u8; 32]>()))
+                .await?;
+
+        let endpoint = Endpoint::builder()
+            .secret_key(SecretKey::from_bytes(&rand::random::<[u8; 32]>()))
+            .bind()
+            .await?;
+
+        let driver = Client::builder()
+            .endpoint(endpoint)
+            .address(addr)
+            .identity(())
+            .opts(
+                EmitterOpts::builder()
+                    .resource_interval(Duration::from_millis(50))
+                    .build(),
+            )
+            .sample_pid(std::process::id())
+            .build()
+            .into_driver();
+
+        let (_emitter, emitter_rx) = emitter::<u16>(10);
+        tokio::spawn(driver.run(emitter_rx, |data| data));
+
+        let sample = time::timeout(Duration::from_secs(5), async {
+            loop {
+                let resp = rx.recv().await.expect("to be open");
+                if let ResponseEvent::Resources(sample) = resp.event {
+                    return sample;
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(sample.pid, std::process::id());
+        assert!(sample.threads > 0);
+
+        Ok(())
+    }
 }
@@ -12,11 +12,14 @@ use n0_error::Location;
 use serde::Serialize;
 use tokio::{
     io::AsyncWriteExt,
-    sync::broadcast,
+    sync::{broadcast, oneshot, watch},
     time::{self, error::Elapsed},
 };
 
-use super::{ALPN, BoxError, EmitterOpts, SinkDriver};
+use super::{
+    ALPN, BoxError, ConnectionState, EmitterOpts, Frame, SinkDriver, Status,
+};
+use crate::api::ResourceSample;
 
 #[derive(Debug, thiserror::Error)]
 enum DriverError {
@@ -69,6 +72,27 @@ impl From<ConnectionError> for DriverError {
     }
 }
 
+// Where the driver sends to. Changing it moves the driver over, anything that
+// is buffered is sent to the new remote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Target {
+    pub(crate) addr: EndpointAddr,
+    // Serialized assertion/identity frame, see `Driver::identity`.
+    pub(crate) identity: Vec<u8>,
+}
+
+impl Target {
+    pub(crate) fn new<T: Serialize>(
+        addr: EndpointAddr,
+        identity: &T,
+    ) -> Result<Self, postcard::Error> {
+        Ok(Self {
+            addr,
+            identity: postcard::to_allocvec(identity)?,
+        })
+    }
+}
+
 #[derive(bon::Builder)]
 pub(crate) struct Driver {
     endpoint: Endpoint,
@@ -79,16 +103,47 @@ pub(crate) struct Driver {
     identity: Vec<u8>,
 
     opts: EmitterOpts,
+    sample_pid: Option<u32>,
+    // When this fires (or the sender is dropped), everything that has already
+    // been buffered is sent and the driver stops.
+    shutdown: Option<oneshot::Receiver<()>>,
+    status: Option<watch::Sender<Status>>,
+    // `None` is skipped, the driver stays where it is.
+    updates: Option<watch::Receiver<Option<Target>>>,
 
     connection: Option<Connection>,
     stream: Option<SendStream>,
 }
 
 impl Driver {
+    // Follows the targets sent on `updates`. Values that were already seen
+    // aren't applied, the driver is expected to start out at the latest one.
+    pub(crate) fn follow(
+        mut self,
+        updates: watch::Receiver<Option<Target>>,
+    ) -> Self {
+        self.updates = Some(updates);
+        self
+    }
+
     const fn is_connected(&self) -> bool {
         self.stream.is_some()
     }
 
+    fn report(&self, update: impl FnOnce(&mut Status)) {
+        if let Some(status) = &self.status {
+            status.send_modify(update);
+        }
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        self.report(|status| status.state = state);
+    }
+
+    fn processed(&self, count: u64) {
+        self.report(|status| status.processed += count);
+    }
+
     async fn connect(&self) -> Result<(Connection, SendStream), DriverError> {
         tracing::debug!("trying to connect ....");
         metrics::counter!("driver.reconnect").increment(1);
@@ -114,6 +169,28 @@ impl Driver {
         }
     }
 
+    // Keeps the new connection and sends the identity frame on it.
+    async fn handshake(
+        &mut self,
+        conn: Connection,
+        stream: SendStream,
+    ) -> Result<(), BoxError> {
+        metrics::counter!("driver.connect").increment(1);
+        metrics::gauge!("driver.connected").set(1.0);
+
+        tracing::debug!(peer = self.addr.id.to_string(), "connected");
+
+        self.connection = Some(conn);
+        self.stream = Some(stream);
+
+        // Avoid borrowing `self` immutably + mutably in one call.
+        let identity = self.identity.clone();
+        self.emit_bytes(&identity).await?;
+
+        self.set_state(ConnectionState::Connected);
+        Ok(())
+    }
+
     async fn emit_bytes(&mut self, bytes: &[u8]) -> Result<(), BoxError> {
         let Some(stream) = self.stream.as_mut() else {
             return Err("failed to get stream, disconnected?".into());
@@ -132,30 +209,206 @@ impl Driver {
         Ok(())
     }
 
-    async fn emit<T>(&mut self, data: T) -> Result<(), BoxError>
+    async fn emit<T>(&mut self, frame: Frame<T>) -> Result<(), BoxError>
     where
         T: Serialize,
     {
-        let bytes = postcard::to_allocvec(&data)?;
+        let bytes = postcard::to_allocvec(&frame)?;
 
         self.emit_bytes(&bytes).await
     }
+
+    // Sends whatever is left in the buffer without waiting for more.
+    async fn flush<T>(
+        &mut self,
+        rx: &mut broadcast::Receiver<Arc<T>>,
+        prepare: &(impl Fn(Arc<T>) -> Arc<T> + Send + Sync),
+    ) where
+        T: Serialize + Send + Sync + 'static,
+    {
+        let mut flushed = 0;
+
+        loop {
+            let data = match rx.try_recv() {
+                Ok(data) => data,
+                Err(broadcast::error::TryRecvError::Lagged(i)) => {
+                    metrics::counter!("driver.lagged").increment(i);
+                    self.processed(i);
+                    continue;
+                }
+                Err(_) => break,
+            };
+
+            let sent = self.emit(Frame::Data(prepare(data).as_ref())).await;
+            self.processed(1);
+
+            if let Err(e) = sent {
+                metrics::counter!("driver.error.send").increment(1);
+                tracing::error!(err = ?e, "failed to send");
+                break;
+            }
+
+            flushed += 1;
+        }
+
+        metrics::counter!("driver.flushed").increment(flushed);
+    }
+
+    async fn emit_sample(&mut self, pid: u32) {
+        let sample = match ResourceSample::read(pid) {
+            Ok(sample) => sample,
+            Err(e) => {
+                metrics::counter!("driver.error.sample").increment(1);
+                tracing::debug!(pid, err = ?e, "unable to sample resources");
+                return;
+            }
+        };
+
+        if let Err(e) = self.emit(Frame::<()>::Resources(sample)).await {
+            metrics::counter!("driver.error.send").increment(1);
+            tracing::error!(err = ?e, "failed to send");
+            return;
+        }
+
+        metrics::counter!("driver.sampled").increment(1);
+    }
+
+    // Lets the sink read everything that was written before the stream goes
+    // away.
+    async fn close_stream(&mut self) {
+        let Some(mut stream) = self.stream.take() else {
+            return;
+        };
+
+        stream
+            .finish()
+            .inspect_err(|e| tracing::warn!(err = ?e, "failed to close stream"))
+            .ok();
+
+        match tokio::time::timeout(Duration::from_secs(5), stream.stopped())
+            .await
+        {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => tracing::warn!(?err, "stopped failed"),
+            Err(_) => tracing::warn!("timed out waiting for stream stop"),
+        }
+    }
+
+    // The current stream is finished either way. The same remote gets a new
+    // stream with the new identity on the existing connection, anything else
+    // is reconnected to.
+    async fn retarget(&mut self, target: Target) -> Result<(), BoxError> {
+        metrics::counter!("driver.retarget").increment(1);
+        tracing::info!(
+            from = self.addr.id.to_string(),
+            to = target.addr.id.to_string(),
+            "switching remote",
+        );
+
+        self.close_stream().await;
+
+        let same = self.addr.id == target.addr.id;
+        self.addr = target.addr;
+        self.identity = target.identity;
+
+        let Some(conn) = self.connection.take() else {
+            return Ok(());
+        };
+
+        if same && conn.close_reason().is_none() {
+            let stream = conn.open_uni().await?;
+            return self.handshake(conn, stream).await;
+        }
+
+        conn.close(0u32.into(), b"retarget");
+        metrics::gauge!("driver.connected").set(0.0);
+        self.set_state(ConnectionState::Connecting);
+
+        Ok(())
+    }
+
+    fn sampler(&self) -> Option<(u32, time::Interval)> {
+        let pid = self.sample_pid?;
+        let mut interval = time::interval(self.opts.resource_interval?);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+        Some((pid, interval))
+    }
+}
+
+async fn shutdown(rx: Option<&mut oneshot::Receiver<()>>) {
+    let Some(rx) = rx else {
+        return future::pending().await;
+    };
+
+    rx.await.ok();
+}
+
+async fn next_target(
+    updates: Option<&mut watch::Receiver<Option<Target>>>,
+) -> Target {
+    let Some(updates) = updates else {
+        return future::pending().await;
+    };
+
+    loop {
+        // Once the sender is gone, there's nothing left to follow.
+        if updates.changed().await.is_err() {
+            return future::pending().await;
+        }
+
+        let target = updates.borrow_and_update().clone();
+        if let Some(target) = target {
+            return target;
+        }
+    }
+}
+
+async fn next_sample(sampler: Option<&mut (u32, time::Interval)>) -> u32 {
+    let Some((pid, interval)) = sampler else {
+        return future::pending().await;
+    };
+
+    interval.tick().await;
+    *pid
 }
 
 #[async_trait::async_trait]
 impl SinkDriver for Driver {
-    async fn run<T>(mut self, mut rx: broadcast::Receiver<Arc<T>>)
-    where
+    async fn run<T, F>(
+        mut self,
+        mut rx: broadcast::Receiver<Arc<T>>,
+        prepare: F,
+    ) where
         T: Serialize + Send + Sync + 'static,
+        F: Fn(Arc<T>) -> Arc<T> + Send + Sync + 'static,
     {
         let mut retry_connect = tokio::time::interval(self.opts.retry_interval);
         retry_connect.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 
+        let mut sampler = self.sampler();
+        let mut stop = self.shutdown.take();
+        let mut updates = self.updates.take();
+
         loop {
             if !self.is_connected() {
-                retry_connect.tick().await;
+                // There's nowhere to flush to, don't hold up shutdown trying
+                // to connect.
+                let connected = tokio::select! {
+                    () = shutdown(stop.as_mut()) => break,
+                    target = next_target(updates.as_mut()) => {
+                        self.addr = target.addr;
+                        self.identity = target.identity;
+                        retry_connect.reset_immediately();
+                        continue;
+                    }
+                    r = async {
+                        retry_connect.tick().await;
+                        self.connect().await
+                    } => r,
+                };
 
-                let (conn, stream) = match self.connect().await {
+                let (conn, stream) = match connected {
                     Ok(v) => v,
                     Err(e) => {
                         tracing::warn!(
@@ -175,17 +428,7 @@ impl SinkDriver for Driver {
                     }
                 };
 
-                metrics::counter!("driver.connect").increment(1);
-                metrics::gauge!("driver.connected").set(1.0);
-
-                tracing::debug!(peer = self.addr.id.to_string(), "connected");
-
-                self.connection = Some(conn);
-                self.stream = Some(stream);
-
-                // Avoid borrowing `self` immutably + mutably in one call.
-                let identity = self.identity.clone();
-                if let Err(e) = self.emit_bytes(&identity).await {
+                if let Err(e) = self.handshake(conn, stream).await {
                     metrics::counter!("driver.error.emit").increment(1);
                     tracing::warn!(err = ?e, "failed to send");
 
@@ -203,6 +446,26 @@ impl SinkDriver for Driver {
 
                     self.stream = None;
                     self.connection = None;
+                    self.set_state(ConnectionState::Connecting);
+                }
+                () = shutdown(stop.as_mut()) => {
+                    self.flush(&mut rx, &prepare).await;
+                    break;
+                }
+                target = next_target(updates.as_mut()) => {
+                    if let Err(e) = self.retarget(target).await {
+                        metrics::counter!("driver.error.emit").increment(1);
+                        tracing::warn!(err = ?e, "failed to switch remote");
+
+                        self.stream = None;
+                        self.connection = None;
+                        self.set_state(ConnectionState::Connecting);
+                    }
+
+                    retry_connect.reset_immediately();
+                }
+                pid = next_sample(sampler.as_mut()) => {
+                    self.emit_sample(pid).await;
                 }
                 r = rx.recv() => {
                     match r {
@@ -210,9 +473,13 @@ impl SinkDriver for Driver {
                         Err(broadcast::error::RecvError::Lagged(i)) => {
                             metrics::counter!("driver.lagged").increment(i);
                             tracing::warn!(count = i, "skipped");
+                            self.processed(i);
                         }
                         Ok(data) => {
-                            if let Err(e) = self.emit(data).await {
+                            let sent = self.emit(Frame::Data(prepare(data).as_ref())).await;
+                            self.processed(1);
+
+                            if let Err(e) = sent {
                                 metrics::counter!("driver.error.send").increment(1);
                                 tracing::error!(err = ?e, "failed to send");
                             }
@@ -226,23 +493,9 @@ impl SinkDriver for Driver {
 
         tracing::info!(peer = self.addr.id.to_string(), "disconnecting...");
 
-        if let Some(mut stream) = self.stream.take() {
-            stream
-                .finish()
-                .inspect_err(
-                    |e| tracing::warn!(err = ?e, "failed to close stream"),
-                )
-                .ok();
-
-            match tokio::time::timeout(Duration::from_secs(5), stream.stopped())
-                .await
-            {
-                Ok(Ok(_)) => {}
-                Ok(Err(err)) => tracing::warn!(?err, "stopped failed"),
-                Err(_) => tracing::warn!("timed out waiting for stream stop"),
-            }
-        }
+        self.close_stream().await;
 
         self.endpoint.close().await;
+        self.set_state(ConnectionState::Stopped);
     }
 }
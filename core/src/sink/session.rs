@@ -8,7 +8,7 @@ use tokio::{
 };
 use uuid::Uuid;
 
-use super::{DisconnectReason, Identity, Response, ResponseEvent};
+use super::{DisconnectReason, Frame, Identity, Response, ResponseEvent};
 use crate::sink::BoxError;
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
@@ -35,7 +35,7 @@ pub(super) struct Session<'a, Assertion, Body> {
     #[builder(default = HEARTBEAT_INTERVAL)]
     heartbeat_interval: Duration,
     identity: Identity<Assertion>,
-    stream: BoxStream<'a, Result<Body, BoxError>>,
+    stream: BoxStream<'a, Result<Frame<Body>, BoxError>>,
     emit: mpsc::Sender<Response<Assertion, Body>>,
 }
 
@@ -91,7 +91,7 @@ where
                     };
 
                     metrics::counter!("sink.message_received").increment(1);
-                    emit_response(&self.emit, self.response(ResponseEvent::Data(req)))
+                    emit_response(&self.emit, self.response(req.into()))
                         .await?;
                 }
 
@@ -122,10 +122,10 @@ mod tests {
 
         let (tx, mut rx) = mpsc::channel::<Response<(), u16>>(32);
         let heartbeat_interval = Duration::from_millis(10);
-        let msg_stream: BoxStream<'static, Result<u16, BoxError>> =
+        let msg_stream: BoxStream<'static, Result<Frame<u16>, BoxError>> =
             stream::once(async move {
                 time::sleep(Duration::from_millis(60)).await;
-                Ok::<u16, BoxError>(7)
+                Ok::<Frame<u16>, BoxError>(Frame::Data(7))
             })
             .boxed();
 
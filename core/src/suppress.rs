@@ -0,0 +1,124 @@
+// Keeps laminar from recording itself. Everything the writer does (binding
+// the endpoint, connecting, iroh's background tasks) runs on a dedicated
+// thread with its own runtime. That thread is marked and the layer drops
+// anything that happens on it. Relying on span parents doesn't work for this,
+// libraries spawn tasks without `in_current_span` or detach from their parent
+// entirely.
+//
+// Iroh can start threads of its own, so its crates are dropped by target as
+// well, no matter where they show up.
+
+use std::{cell::Cell, fs::File, future::Future, sync::Arc};
+
+use tokio::{runtime, sync::oneshot, task::JoinHandle};
+use tracing::{Dispatch, level_filters::LevelFilter};
+
+thread_local! {
+    static SUPPRESSED: Cell<bool> = const { Cell::new(false) };
+}
+
+const TARGETS: &[&str] = &[
+    "laminar_stream::sink",
+    "iroh",
+    "iroh_base",
+    "iroh_metrics",
+    "iroh_quinn",
+    "iroh_quinn_proto",
+    "iroh_quinn_udp",
+    "iroh_relay",
+    "n0_future",
+    "n0_watcher",
+    "netwatch",
+    "portmapper",
+    "quinn",
+    "quinn_proto",
+    "quinn_udp",
+    "swarm_discovery",
+];
+
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn is_suppressed(target: &str) -> bool {
+    is_thread_suppressed()
+        || TARGETS.iter().any(|prefix| {
+            target
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+}
+
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn is_thread_suppressed() -> bool {
+    SUPPRESSED.get()
+}
+
+// Marks the current thread until dropped. The layer holds one while it does
+// its own work so that anything logged along the way isn't fed back into it.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) struct Guard(bool);
+
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn enter() -> Guard {
+    Guard(SUPPRESSED.replace(true))
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        SUPPRESSED.set(self.0);
+    }
+}
+
+// Runs `future` on a new thread with its own current-thread runtime. Tasks
+// spawned by the future (or by libraries it calls) end up on the same thread
+// and are suppressed as well. The caller's dispatcher is carried over so
+// output still shows up in other layers, eg a terminal formatter.
+//
+// While output is captured, whatever that formatter writes to stdout or
+// stderr would be captured right back. `output` is the original stderr then,
+// and the thread logs there with a plain formatter instead.
+//
+// The runtime lives until the future completes or the handle is aborted.
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn spawn<F>(
+    name: &str,
+    output: Option<Arc<File>>,
+    future: F,
+) -> std::io::Result<JoinHandle<F::Output>>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let dispatch = output.map_or_else(
+        || tracing::dispatcher::get_default(Clone::clone),
+        |output| {
+            Dispatch::new(
+                tracing_subscriber::fmt()
+                    .with_writer(output)
+                    .with_ansi(false)
+                    .with_max_level(LevelFilter::current())
+                    .finish(),
+            )
+        },
+    );
+    let rt = runtime::Builder::new_current_thread()
+        .enable_all()
+        .on_thread_start(|| SUPPRESSED.set(true))
+        .build()?;
+
+    let (done_tx, done_rx) = oneshot::channel::<()>();
+    let handle = rt.spawn(async move {
+        let _done = done_tx;
+        future.await
+    });
+
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            SUPPRESSED.set(true);
+            let _dispatch = tracing::dispatcher::set_default(&dispatch);
+
+            rt.block_on(done_rx).ok();
+            rt.shutdown_background();
+        })?;
+
+    Ok(handle)
+}
@@ -0,0 +1,62 @@
+/*
+ * C API for sending records to a Laminar sink.
+ *
+ * Every function is safe to call from any thread. Strings are UTF-8 and NUL
+ * terminated, and are copied before the call returns.
+ */
+
+#ifndef LAMINAR_H
+#define LAMINAR_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct laminar_client laminar_client;
+
+/* Return values, anything other than LAMINAR_OK has a message available from
+ * laminar_last_error. A panic inside the library is LAMINAR_ERR_INTERNAL. */
+#define LAMINAR_OK 0
+#define LAMINAR_ERR_INVALID_ARGUMENT -1
+#define LAMINAR_ERR_CLOSED -2
+#define LAMINAR_ERR_TIMEOUT -3
+#define LAMINAR_ERR_INTERNAL -4
+
+#define LAMINAR_LEVEL_NONE -1
+#define LAMINAR_LEVEL_TRACE 0
+#define LAMINAR_LEVEL_DEBUG 1
+#define LAMINAR_LEVEL_INFO 2
+#define LAMINAR_LEVEL_WARN 3
+#define LAMINAR_LEVEL_ERROR 4
+
+/* Starts a writer. `config` is the `[layer]` table of the config file as TOML,
+ * eg `remote = "<public key>"`. When NULL, the config is loaded from
+ * ~/.config/laminar/config.toml and LAMINAR_* environment variables.
+ *
+ * Returns NULL on failure. */
+laminar_client *laminar_init(const char *config);
+
+/* Queues a record. `target` and `fields_json` may be NULL, `fields_json` has
+ * to be a JSON object otherwise. */
+int laminar_send(laminar_client *client, int level, const char *target,
+                 const char *message, const char *fields_json);
+
+/* Waits until everything queued so far has been sent, at most `timeout_ms`.
+ * Returns LAMINAR_ERR_TIMEOUT when there wasn't a connection in time. */
+int laminar_flush(laminar_client *client, uint32_t timeout_ms);
+
+/* Flushes, waiting a few seconds at most, and stops the writer. `client` is
+ * freed and can't be used afterwards. NULL is ignored. */
+void laminar_shutdown(laminar_client *client);
+
+/* The error from the last failed call on this thread, NULL if there wasn't
+ * one. Valid until the next call on the same thread. */
+const char *laminar_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LAMINAR_H */
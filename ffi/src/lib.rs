@@ -0,0 +1,428 @@
+// C API over `laminar_stream::Client`, see `include/laminar.h` for the
+// contract. Every function catches its own errors and panics, see `guard`,
+// nothing unwinds across the boundary. Errors are reported with a status code
+// and a message that can be fetched with `laminar_last_error`.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+    time::Duration,
+};
+
+use figment::{
+    Figment,
+    providers::{Format, Toml},
+};
+use laminar_stream::{
+    Client, Config, Level, Record, SourceProcess, config::LayerConfig,
+};
+use tokio::runtime::{Handle, Runtime};
+
+pub const LAMINAR_OK: c_int = 0;
+pub const LAMINAR_ERR_INVALID_ARGUMENT: c_int = -1;
+pub const LAMINAR_ERR_CLOSED: c_int = -2;
+pub const LAMINAR_ERR_TIMEOUT: c_int = -3;
+pub const LAMINAR_ERR_INTERNAL: c_int = -4;
+
+pub const LAMINAR_LEVEL_NONE: c_int = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(err: impl Display) {
+    // Interior NULs would truncate the message anyways.
+    let msg = err.to_string().replace('\0', " ");
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = CString::new(msg).ok();
+    });
+}
+
+fn clear_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+// Blocking on or dropping the client's runtime panics on a thread that is
+// already running one, eg when called back from async code. Those are moved
+// to a thread of their own.
+fn off_runtime<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    if Handle::try_current().is_err() {
+        return f();
+    }
+
+    std::thread::scope(|scope| scope.spawn(f).join())
+        .unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+// Runs the body of an entry point, a panic is reported as an internal error
+// and `on_panic` is returned instead. Unwinding into C is undefined.
+fn guard<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let msg = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown");
+        set_error(format!("internal error: {msg}"));
+
+        on_panic
+    })
+}
+
+// `Client` can't be shared between threads by itself, C callers expect to be
+// able to log from anywhere.
+#[allow(non_camel_case_types)]
+#[derive(Debug)]
+pub struct laminar_client {
+    client: Mutex<Client>,
+    // Only drives the timers for `laminar_flush`, the writer has its own.
+    runtime: Runtime,
+}
+
+impl laminar_client {
+    fn new(config: LayerConfig) -> eyre::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+
+        let client = Client::builder()
+            .config(config)
+            .source(SourceProcess::default())
+            .build()?;
+
+        Ok(Self {
+            client: Mutex::new(client),
+            runtime,
+        })
+    }
+}
+
+fn layer_config(config: Option<&str>) -> eyre::Result<LayerConfig> {
+    Ok(match config {
+        None => Config::load()?.layer(),
+        Some(config) => Figment::from(Toml::string(config)).extract()?,
+    })
+}
+
+// NULL is `None`, invalid UTF-8 is an error.
+unsafe fn optional_str<'a>(
+    name: &str,
+    value: *const c_char,
+) -> Result<Option<&'a str>, String> {
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    // SAFETY: non-null and NUL terminated per the caller's contract.
+    unsafe { CStr::from_ptr(value) }
+        .to_str()
+        .map(Some)
+        .map_err(|e| format!("{name} is not valid UTF-8: {e}"))
+}
+
+/// Starts a writer, returns NULL on failure.
+///
+/// # Safety
+///
+/// `config` must be NULL or a NUL terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn laminar_init(
+    config: *const c_char,
+) -> *mut laminar_client {
+    clear_error();
+
+    guard(std::ptr::null_mut(), || {
+        // SAFETY: forwarded from the caller.
+        let config = match unsafe { optional_str("config", config) } {
+            Ok(config) => config,
+            Err(err) => {
+                set_error(err);
+                return std::ptr::null_mut();
+            }
+        };
+
+        match layer_config(config).and_then(laminar_client::new) {
+            Ok(client) => Box::into_raw(Box::new(client)),
+            Err(err) => {
+                set_error(format!("{err:#}"));
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+fn record(
+    level: c_int,
+    target: Option<&str>,
+    message: Option<&str>,
+    fields: Option<&str>,
+) -> Result<Record, String> {
+    let level = if level == LAMINAR_LEVEL_NONE {
+        None
+    } else {
+        Some(
+            u8::try_from(level)
+                .ok()
+                .and_then(Level::from_repr)
+                .filter(|level| *level != Level::Off)
+                .ok_or_else(|| format!("invalid level {level}"))?,
+        )
+    };
+
+    let Some(message) = message else {
+        return Err("message is NULL".to_string());
+    };
+
+    // Records from `tracing` carry their metadata under `tracing`, these get
+    // the same shape so the sink can treat them alike.
+    let mut fields = match fields.map(serde_json::from_str) {
+        None => serde_json::Map::new(),
+        Some(Ok(serde_json::Value::Object(fields))) => fields,
+        Some(Ok(_)) => return Err("fields is not a JSON object".to_string()),
+        Some(Err(err)) => return Err(format!("fields is not JSON: {err}")),
+    };
+    if let Some(target) = target {
+        fields
+            .entry("tracing")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+            .map(|tracing| tracing.insert("target".into(), target.into()));
+    }
+
+    Ok(Record::builder()
+        .maybe_level(level)
+        .maybe_source(target.map(str::to_string))
+        .message(message.to_string())
+        .fields(serde_json::Value::Object(fields).to_string())
+        .build())
+}
+
+/// Queues a record.
+///
+/// # Safety
+///
+/// `client` must be NULL or a pointer returned by `laminar_init` that hasn't
+/// been shut down. The strings must be NULL or NUL terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn laminar_send(
+    client: *mut laminar_client,
+    level: c_int,
+    target: *const c_char,
+    message: *const c_char,
+    fields_json: *const c_char,
+) -> c_int {
+    clear_error();
+
+    // SAFETY: forwarded from the caller.
+    guard(LAMINAR_ERR_INTERNAL, || unsafe {
+        send(client, level, target, message, fields_json)
+    })
+}
+
+// See `laminar_send`.
+unsafe fn send(
+    client: *mut laminar_client,
+    level: c_int,
+    target: *const c_char,
+    message: *const c_char,
+    fields_json: *const c_char,
+) -> c_int {
+    // SAFETY: valid or NULL per the caller's contract.
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        set_error("client is NULL");
+        return LAMINAR_ERR_INVALID_ARGUMENT;
+    };
+
+    // SAFETY: forwarded from the caller.
+    let record = unsafe {
+        optional_str("target", target).and_then(|target| {
+            record(
+                level,
+                target,
+                optional_str("message", message)?,
+                optional_str("fields_json", fields_json)?,
+            )
+        })
+    };
+    let record = match record {
+        Ok(record) => record,
+        Err(err) => {
+            set_error(err);
+            return LAMINAR_ERR_INVALID_ARGUMENT;
+        }
+    };
+
+    let Ok(inner) = client.client.lock() else {
+        set_error("client is poisoned");
+        return LAMINAR_ERR_INTERNAL;
+    };
+
+    match inner.send(record) {
+        Ok(()) => LAMINAR_OK,
+        Err(err) => {
+            set_error(err);
+            LAMINAR_ERR_CLOSED
+        }
+    }
+}
+
+/// Waits for everything queued so far to be sent.
+///
+/// # Safety
+///
+/// `client` must be NULL or a pointer returned by `laminar_init` that hasn't
+/// been shut down.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn laminar_flush(
+    client: *mut laminar_client,
+    timeout_ms: u32,
+) -> c_int {
+    clear_error();
+
+    // SAFETY: forwarded from the caller.
+    guard(LAMINAR_ERR_INTERNAL, || unsafe {
+        flush(client, timeout_ms)
+    })
+}
+
+// See `laminar_flush`.
+unsafe fn flush(client: *mut laminar_client, timeout_ms: u32) -> c_int {
+    // SAFETY: valid or NULL per the caller's contract.
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        set_error("client is NULL");
+        return LAMINAR_ERR_INVALID_ARGUMENT;
+    };
+
+    // The lock isn't held while waiting, other threads can keep sending.
+    let Ok(flush) = client.client.lock().map(|inner| inner.flush()) else {
+        set_error("client is poisoned");
+        return LAMINAR_ERR_INTERNAL;
+    };
+
+    let timeout = Duration::from_millis(timeout_ms.into());
+    match off_runtime(|| {
+        client
+            .runtime
+            .block_on(async { tokio::time::timeout(timeout, flush).await })
+    }) {
+        Ok(()) => LAMINAR_OK,
+        Err(err) => {
+            set_error(err);
+            LAMINAR_ERR_TIMEOUT
+        }
+    }
+}
+
+/// Stops the writer and frees `client`.
+///
+/// # Safety
+///
+/// `client` must be NULL or a pointer returned by `laminar_init` that hasn't
+/// been shut down. It can't be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn laminar_shutdown(client: *mut laminar_client) {
+    clear_error();
+
+    if client.is_null() {
+        return;
+    }
+
+    // SAFETY: allocated by `laminar_init`, ownership is handed back here.
+    // Dropping the client flushes what's left.
+    let client = unsafe { Box::from_raw(client) };
+    guard((), || off_runtime(|| drop(client)));
+}
+
+/// The error from the last failed call on this thread, NULL if there wasn't
+/// one. The string is owned by the library and valid until the next call on
+/// the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn laminar_last_error() -> *const c_char {
+    guard(std::ptr::null(), || {
+        LAST_ERROR.with(|last| {
+            last.borrow()
+                .as_ref()
+                .map_or(std::ptr::null(), |msg| msg.as_ptr())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    // Only needed by `tests/c_api.rs`.
+    use iroh as _;
+    use rand as _;
+
+    use super::*;
+
+    #[test]
+    fn test_record() -> Result<(), String> {
+        let info = record(
+            2,
+            Some("billing"),
+            Some("charged"),
+            Some(r#"{"amount": 42}"#),
+        )?;
+
+        assert_eq!(info.level, Some(Level::Info));
+        assert_eq!(info.source.as_deref(), Some("billing"));
+
+        let fields: serde_json::Value =
+            serde_json::from_str(&info.fields).map_err(|e| e.to_string())?;
+        assert_eq!(fields["amount"], 42);
+        assert_eq!(fields["tracing"]["target"], "billing");
+
+        assert!(record(-1, None, Some("hi"), None)?.level.is_none());
+        assert!(record(5, None, Some("hi"), None).is_err());
+        assert!(record(2, None, None, None).is_err());
+        assert!(record(2, None, Some("hi"), Some("[1]")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_error() {
+        // SAFETY: a NUL terminated literal.
+        let client = unsafe { laminar_init(c"remote = 1".as_ptr()) };
+        assert!(client.is_null());
+        assert!(!laminar_last_error().is_null());
+
+        // SAFETY: NULL is allowed.
+        assert_eq!(
+            unsafe { laminar_flush(std::ptr::null_mut(), 0) },
+            LAMINAR_ERR_INVALID_ARGUMENT
+        );
+    }
+
+    #[test]
+    fn test_guard() {
+        assert_eq!(guard(LAMINAR_ERR_INTERNAL, || panic!("boom")), -4);
+
+        // SAFETY: set just above, on this thread.
+        let msg = unsafe { CStr::from_ptr(laminar_last_error()) };
+        assert_eq!(msg.to_str(), Ok("internal error: boom"));
+    }
+
+    #[test]
+    fn test_flush_in_runtime() -> eyre::Result<()> {
+        // SAFETY: a NUL terminated literal.
+        let client = unsafe { laminar_init(c"key = \"ephemeral\"".as_ptr()) };
+        assert!(!client.is_null());
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        // SAFETY: returned by `laminar_init` above.
+        let status =
+            runtime.block_on(async { unsafe { laminar_flush(client, 100) } });
+        assert_eq!(status, LAMINAR_OK);
+
+        // SAFETY: not used afterwards.
+        runtime.block_on(async { unsafe { laminar_shutdown(client) } });
+
+        Ok(())
+    }
+}
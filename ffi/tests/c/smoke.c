@@ -0,0 +1,67 @@
+/* Exercises the C API. The remote from argv[1] is never reachable, the
+ * config in LAMINAR_CONFIG points at a sink that is checked by c_api.rs. */
+
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "laminar.h"
+
+int main(int argc, char **argv) {
+  char config[256];
+
+  if (argc != 2) {
+    fprintf(stderr, "usage: %s <remote>\n", argv[0]);
+    return 2;
+  }
+
+  assert(laminar_init("remote = [") == NULL);
+  assert(laminar_last_error() != NULL);
+
+  /* An ephemeral key keeps the test from leaving a key file behind. */
+  snprintf(config, sizeof(config), "remote = \"%s\"\nkey = \"ephemeral\"",
+           argv[1]);
+  laminar_client *client = laminar_init(config);
+  if (client == NULL) {
+    fprintf(stderr, "init: %s\n", laminar_last_error());
+    return 1;
+  }
+  assert(laminar_last_error() == NULL);
+
+  assert(laminar_send(client, LAMINAR_LEVEL_INFO, "smoke", "hello",
+                      "{\"answer\": 42}") == LAMINAR_OK);
+  assert(laminar_send(client, LAMINAR_LEVEL_NONE, NULL, "no fields", NULL) ==
+         LAMINAR_OK);
+
+  assert(laminar_send(client, LAMINAR_LEVEL_INFO, NULL, "bad", "[1]") ==
+         LAMINAR_ERR_INVALID_ARGUMENT);
+  assert(strstr(laminar_last_error(), "object") != NULL);
+  assert(laminar_send(client, 9, NULL, "bad", NULL) ==
+         LAMINAR_ERR_INVALID_ARGUMENT);
+  assert(laminar_send(NULL, LAMINAR_LEVEL_INFO, NULL, "bad", NULL) ==
+         LAMINAR_ERR_INVALID_ARGUMENT);
+
+  /* Nothing can be written without a connection. */
+  assert(laminar_flush(client, 50) == LAMINAR_ERR_TIMEOUT);
+
+  laminar_shutdown(client);
+  laminar_shutdown(NULL);
+
+  /* NULL loads the config file, the same as a Rust writer would. */
+  client = laminar_init(NULL);
+  if (client == NULL) {
+    fprintf(stderr, "init: %s\n", laminar_last_error());
+    return 1;
+  }
+
+  assert(laminar_send(client, LAMINAR_LEVEL_WARN, "smoke", "hello",
+                      "{\"answer\": 42}") == LAMINAR_OK);
+  if (laminar_flush(client, 10000) != LAMINAR_OK) {
+    fprintf(stderr, "flush: %s\n", laminar_last_error());
+    return 1;
+  }
+
+  laminar_shutdown(client);
+
+  return 0;
+}
@@ -0,0 +1,153 @@
+// Builds `tests/c/smoke.c` against the library and header, then checks that
+// the record it sends arrives at a sink the same way as one from a Rust
+// writer. Needs a C compiler.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use eyre::{Result, bail};
+use figment as _;
+use iroh::{Endpoint, EndpointAddr, SecretKey, protocol::Router};
+use laminar as _;
+use laminar_stream::{
+    Claims, LabelSource, Level, Record, detect_labels,
+    sink::{ALPN, Response, ResponseEvent, Sink},
+};
+use tokio::{sync::mpsc, time};
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Reachable over loopback, without waiting for discovery or relays.
+async fn local_sink() -> Result<(
+    mpsc::Receiver<Response<Claims, Record>>,
+    Router,
+    EndpointAddr,
+)> {
+    let key = SecretKey::from_bytes(&rand::random::<[u8; 32]>());
+    let endpoint = Endpoint::builder().secret_key(key).bind().await?;
+
+    let addr = endpoint
+        .bound_sockets()
+        .into_iter()
+        .filter(std::net::SocketAddr::is_ipv4)
+        .fold(EndpointAddr::new(endpoint.id()), |addr, socket| {
+            addr.with_ip_addr(
+                (std::net::Ipv4Addr::LOCALHOST, socket.port()).into(),
+            )
+        });
+
+    let (handler, rx) = Sink::<Claims, Record>::build().split();
+    let router = Router::builder(endpoint).accept(ALPN, handler).spawn();
+
+    Ok((rx, router, addr))
+}
+
+fn compile(manifest: &Path, dir: &Path, binary: &Path) {
+    let status = Command::new("cc")
+        .arg(manifest.join("tests/c/smoke.c"))
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg("-L")
+        .arg(dir)
+        .arg("-llaminar")
+        .arg(format!("-Wl,-rpath,{}", dir.display()))
+        .arg("-o")
+        .arg(binary)
+        .status()
+        .expect("a C compiler, `cc`");
+    assert!(status.success(), "compiling smoke.c failed");
+}
+
+#[tokio::test]
+async fn test_c_api() -> Result<()> {
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // Cargo builds the library next to the test binary, in
+    // `target/<profile>/deps`.
+    let dir = std::env::current_exe()?
+        .parent()
+        .expect("deps dir")
+        .to_path_buf();
+    let binary = dir.join("laminar_c_smoke");
+    compile(&manifest, &dir, &binary);
+
+    let (mut rx, _router, addr) = local_sink().await?;
+    let socket = addr.ip_addrs().next().expect("a loopback address");
+
+    // The config file is what a C program would have, writers find the sink
+    // through the address book.
+    let config = dir.join(format!("laminar_c_smoke_{}.toml", addr.id));
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+            [layer]
+            remote = "sink"
+            key = "ephemeral"
+            display_name = "smoke"
+            detect_labels = ["cwd", "git"]
+            labels = {{ suite = "c_api" }}
+            network.mode = "local"
+
+            [peers.sink]
+            key = "{}"
+            addrs = ["{socket}"]
+            "#,
+            addr.id,
+        ),
+    )?;
+
+    let child = Command::new(&binary)
+        .arg("3b6a27bcceb6a42d62a3a8d02a6f0d73653215771de243a63ac048a18b59da29")
+        .env("LAMINAR_CONFIG", &config)
+        .env_remove("LAMINAR_PROFILE")
+        .spawn()?;
+    let pid = child.id();
+    let status = tokio::task::spawn_blocking(move || child.wait_with_output());
+
+    let resp = time::timeout(RECEIVE_TIMEOUT, async {
+        loop {
+            let resp = rx.recv().await.expect("to be open");
+            if let ResponseEvent::Data(record) = &resp.event
+                && record.message == "hello"
+            {
+                return resp;
+            }
+        }
+    })
+    .await;
+
+    let status = status.await??.status;
+    std::fs::remove_file(&config)?;
+    assert!(status.success(), "smoke test failed");
+
+    let resp = resp?;
+    let ResponseEvent::Data(record) = resp.event else {
+        bail!("expected a record: {:?}", resp.event);
+    };
+
+    assert_eq!(record.level, Some(Level::Warn));
+    assert_eq!(record.source.as_deref(), Some("smoke"));
+    let fields: serde_json::Value = serde_json::from_str(&record.fields)?;
+    assert_eq!(
+        fields,
+        serde_json::json!({"answer": 42, "tracing": {"target": "smoke"}})
+    );
+
+    // What `Writer` claims for the same config, the smoke test runs in the
+    // same directory as this one.
+    let identity = resp.identity.assertion;
+    let mut labels = detect_labels(&[LabelSource::Cwd, LabelSource::Git], None);
+    labels.insert("suite".into(), "c_api".into());
+
+    assert_eq!(identity.hostname, Claims::builder().build().hostname);
+    assert_eq!(identity.display_name.as_deref(), Some("smoke"));
+    assert_eq!(identity.labels, labels);
+    let source = identity.source.expect("a source process");
+    assert_eq!(source.name, "laminar_c_smoke");
+    assert_eq!(source.pid, pid);
+
+    Ok(())
+}